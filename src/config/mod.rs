@@ -1,5 +1,14 @@
-use mongodb::{Client, Database};
+use mongodb::bson::{Document, doc};
+use mongodb::options::IndexOptions;
+use mongodb::{Client, Database, IndexModel};
 use std::env;
+use std::time::Duration;
+use url::Url;
+
+use crate::models::user::User;
+use crate::utils::hash::hash_password;
+use crate::utils::role::{ROLE_ADMIN, default_user_roles};
+use crate::utils::scope::default_user_scopes;
 
 pub async fn connect_db() -> Database {
     let mogodb_uri = env::var("MONGODB_URI").expect("MONGODB_URI must be set");
@@ -9,3 +18,331 @@ pub async fn connect_db() -> Database {
 
     client.database("rust_oauth2")
 }
+
+/// Public base URL of this service, used to build absolute endpoint URLs
+pub fn base_url() -> String {
+    env::var("BASE_URL")
+        .unwrap_or_else(|_| "http://localhost:8080".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// OpenID Connect issuer identifier (`iss`), defaults to the base URL
+pub fn issuer() -> String {
+    env::var("JWT_ISSUER").unwrap_or_else(|_| base_url())
+}
+
+/// Audience (`aud`) of the access and refresh tokens, defaults to the issuer
+pub fn audience() -> String {
+    env::var("JWT_AUDIENCE").unwrap_or_else(|_| issuer())
+}
+
+/// Name shown for this service in authenticator apps (`TOTP_ISSUER`)
+pub fn totp_issuer() -> String {
+    env::var("TOTP_ISSUER").unwrap_or_else(|_| "Rust OAuth2 API".to_string())
+}
+
+/// WebAuthn relying party id (`WEBAUTHN_RP_ID`), defaults to the host of the base URL
+pub fn webauthn_rp_id() -> String {
+    env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| {
+        Url::parse(&base_url())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_else(|| "localhost".to_string())
+    })
+}
+
+/// Origin the browser reports in WebAuthn ceremonies (`WEBAUTHN_ORIGIN`), defaults to the base URL
+pub fn webauthn_origin() -> String {
+    env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| base_url())
+}
+
+/// Relying party name shown by authenticators (`WEBAUTHN_RP_NAME`), defaults to `TOTP_ISSUER`
+pub fn webauthn_rp_name() -> String {
+    env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| totp_issuer())
+}
+
+/// Whether logins are refused until the email address is verified (`REQUIRE_EMAIL_VERIFICATION`)
+pub fn email_verification_required() -> bool {
+    env::var("REQUIRE_EMAIL_VERIFICATION").is_ok_and(|v| v == "true" || v == "1")
+}
+
+/// Create the unique and TTL indexes the OAuth2 collections rely on
+pub async fn create_indexes(db: &Database) {
+    let unique = || IndexOptions::builder().unique(true).build();
+    // unique where the field is present (revoked_tokens hold either a jti or a sid)
+    let unique_sparse = || IndexOptions::builder().unique(true).sparse(true).build();
+    let expire_at_date = || {
+        IndexOptions::builder()
+            .expire_after(Duration::from_secs(0))
+            .build()
+    };
+
+    let indexes: Vec<(&str, IndexModel)> = vec![
+        (
+            "clients",
+            IndexModel::builder()
+                .keys(doc! { "client_id": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "authorization_codes",
+            IndexModel::builder()
+                .keys(doc! { "code_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "authorization_codes",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "device_codes",
+            IndexModel::builder()
+                .keys(doc! { "device_code_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "device_codes",
+            IndexModel::builder()
+                .keys(doc! { "user_code": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            // kept for a while after expiry so polling devices get expired_token
+            "device_codes",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(60 * 60))
+                        .build(),
+                )
+                .build(),
+        ),
+        (
+            "email_verifications",
+            IndexModel::builder()
+                .keys(doc! { "token_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "email_verifications",
+            IndexModel::builder().keys(doc! { "user_id": 1 }).build(),
+        ),
+        (
+            "email_verifications",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "mfa_challenges",
+            IndexModel::builder()
+                .keys(doc! { "token_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "mfa_challenges",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "login_attempts",
+            IndexModel::builder()
+                .keys(doc! { "email": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "login_attempts",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "password_resets",
+            IndexModel::builder()
+                .keys(doc! { "token_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "password_resets",
+            IndexModel::builder().keys(doc! { "user_id": 1 }).build(),
+        ),
+        (
+            "password_resets",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "refresh_tokens",
+            IndexModel::builder()
+                .keys(doc! { "jti": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "refresh_tokens",
+            IndexModel::builder().keys(doc! { "family_id": 1 }).build(),
+        ),
+        (
+            "refresh_tokens",
+            IndexModel::builder().keys(doc! { "user_id": 1 }).build(),
+        ),
+        (
+            "refresh_tokens",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "sessions",
+            IndexModel::builder()
+                .keys(doc! { "session_id": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "sessions",
+            IndexModel::builder().keys(doc! { "user_id": 1 }).build(),
+        ),
+        (
+            "sessions",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "revoked_tokens",
+            IndexModel::builder()
+                .keys(doc! { "jti": 1 })
+                .options(unique_sparse())
+                .build(),
+        ),
+        (
+            "revoked_tokens",
+            IndexModel::builder()
+                .keys(doc! { "sid": 1 })
+                .options(unique_sparse())
+                .build(),
+        ),
+        (
+            "revoked_tokens",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+        (
+            "users",
+            IndexModel::builder()
+                .keys(doc! { "webauthn_credentials.credential_id": 1 })
+                .options(unique_sparse())
+                .build(),
+        ),
+        (
+            "webauthn_challenges",
+            IndexModel::builder()
+                .keys(doc! { "challenge_hash": 1 })
+                .options(unique())
+                .build(),
+        ),
+        (
+            "webauthn_challenges",
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(expire_at_date())
+                .build(),
+        ),
+    ];
+
+    for (collection, index) in indexes {
+        db.collection::<Document>(collection)
+            .create_index(index)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create index on {}: {}", collection, e));
+    }
+}
+
+/// Create the first administrator from `ADMIN_EMAIL`/`ADMIN_PASSWORD` (and optional
+/// `ADMIN_USERNAME`) when the `users` collection is empty. Later admins are promoted
+/// through `PUT /api/admin/users/{email}/roles`.
+pub async fn bootstrap_admin(db: &Database) {
+    let (Ok(email), Ok(password)) = (env::var("ADMIN_EMAIL"), env::var("ADMIN_PASSWORD")) else {
+        return;
+    };
+
+    let users = db.collection::<User>("users");
+    let user_count = users
+        .count_documents(doc! {})
+        .await
+        .expect("Failed to count users");
+    if user_count > 0 {
+        let admin_count = users
+            .count_documents(doc! { "roles": ROLE_ADMIN })
+            .await
+            .expect("Failed to count administrators");
+        if admin_count == 0 {
+            println!(
+                "-> config - no administrator, but ADMIN_EMAIL only applies to an empty database"
+            );
+        }
+        return;
+    }
+
+    let mut roles = default_user_roles();
+    roles.push(ROLE_ADMIN.to_string());
+    let admin = User {
+        id: None,
+        username: env::var("ADMIN_USERNAME").unwrap_or_else(|_| "admin".to_string()),
+        email: email.clone(),
+        password: hash_password(&password).expect("Failed to hash ADMIN_PASSWORD"),
+        email_verified: true,
+        scopes: default_user_scopes(),
+        roles,
+        token_version: 0,
+        totp_secret: None,
+        totp_pending_secret: None,
+        totp_last_step: 0,
+        recovery_codes: Vec::new(),
+        webauthn_credentials: Vec::new(),
+    };
+    users
+        .insert_one(admin)
+        .await
+        .expect("Failed to create the first administrator");
+    println!("-> config - created administrator {}", email);
+}
+
+/// A fresh database on `MONGODB_URI` with every index, for tests that drop it when done
+#[cfg(test)]
+pub async fn test_db() -> Database {
+    let uri = env::var("MONGODB_URI").expect("MONGODB_URI must be set");
+    let client = Client::with_uri_str(uri)
+        .await
+        .expect("Failed to connect to MongoDB");
+    let db = client.database(&format!(
+        "rust_oauth2_test_{}",
+        uuid::Uuid::new_v4().simple()
+    ));
+    create_indexes(&db).await;
+    db
+}
@@ -0,0 +1,138 @@
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::{Error, HttpResponse, web};
+use mongodb::Database;
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::user::User;
+use crate::utils::audit::record_security_event;
+use crate::utils::lockout::unlock_account;
+use crate::utils::role::{ROLE_ADMIN, SUPPORTED_ROLES};
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "roles": ["user", "admin"] }))]
+pub struct UserRolesRequest {
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({ "email": "user@example.com", "roles": ["user", "admin"] }))]
+pub struct UserRolesResponse {
+    pub email: String,
+    pub roles: Vec<String>,
+}
+
+//-----------------------------------------
+/// Replace a user's roles (admin only)
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{email}/roles",
+    operation_id = "set_user_roles",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("email" = String, Path, description = "Email of the user")
+    ),
+    request_body = UserRolesRequest,
+    responses(
+        (status = 200, description = "Roles updated, effective from the user's next access token", body = UserRolesResponse),
+        (status = 400, description = "Unknown role, or an admin removing their own admin role"),
+        (status = 401, description = "Unauthorized - Missing or invalid token"),
+        (status = 403, description = "Forbidden - not an administrator"),
+        (status = 404, description = "No such user")
+    )
+)]
+// Protected route, admin only
+pub async fn set_user_roles(
+    db: web::Data<Database>,
+    admin: AuthenticatedUser,
+    path: web::Path<String>,
+    payload: web::Json<UserRolesRequest>,
+) -> Result<HttpResponse, Error> {
+    let email = path.into_inner();
+    let mut roles: Vec<String> = Vec::new();
+    for role in &payload.roles {
+        if !SUPPORTED_ROLES.contains(&role.as_str()) {
+            return Err(ErrorBadRequest(format!("Unknown role: {}", role)));
+        }
+        if !roles.contains(role) {
+            roles.push(role.clone());
+        }
+    }
+
+    let users = db.collection::<User>("users");
+    let user = users
+        .find_one(doc! { "email": &email })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorNotFound("User not found"))?;
+
+    // keeps at least one administrator around
+    if user.subject() == admin.user_id() && !roles.iter().any(|role| role == ROLE_ADMIN) {
+        return Err(ErrorBadRequest("You can't remove your own admin role"));
+    }
+
+    users
+        .update_one(
+            doc! { "_id": user.id },
+            doc! { "$set": { "roles": &roles } },
+        )
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(UserRolesResponse { email, roles }))
+}
+
+//-----------------------------------------
+/// Lift a lockout and reset the user's failed login count (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{email}/unlock",
+    operation_id = "unlock_user",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("email" = String, Path, description = "Email of the user")
+    ),
+    responses(
+        (status = 204, description = "Account unlocked"),
+        (status = 401, description = "Unauthorized - Missing or invalid token"),
+        (status = 403, description = "Forbidden - not an administrator"),
+        (status = 404, description = "No such user")
+    )
+)]
+// Protected route, admin only
+pub async fn unlock_user(
+    db: web::Data<Database>,
+    admin: AuthenticatedUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let email = path.into_inner();
+    let user = db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &email })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorNotFound("User not found"))?;
+
+    unlock_account(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+
+    record_security_event(
+        &db,
+        "account_unlocked",
+        Some(&user.email),
+        None,
+        format!("Unlocked by administrator {}", admin.user_id()),
+    )
+    .await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,15 +1,33 @@
-use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
-use actix_web::http::header::AUTHORIZATION;
-use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, post, web};
-use jsonwebtoken::{Algorithm, DecodingKey, TokenData, Validation, decode};
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::{Error, HttpRequest, HttpResponse, post, web};
+use chrono::Duration;
 use mongodb::Database;
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::models::user::User;
-use crate::utils::hash::{hash_password, verify_password};
-use crate::utils::jwt::{Claims, create_jwt, extract_email_from_jwt};
+use crate::config::email_verification_required;
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::client::Client;
+use crate::models::user::{User, subject_filter};
+use crate::utils::email_verification::send_verification_email;
+use crate::utils::hash::{hash_password, verify_account_password};
+use crate::utils::jwt::{
+    ACCESS_TOKEN_MINUTES, Claims, REFRESH_TOKEN_MINUTES, create_jwt, verify_jwt,
+};
+use crate::utils::lockout::{
+    locked_for, locked_response, record_login_failure, record_login_success,
+};
+use crate::utils::mailer::Mailer;
+use crate::utils::mfa::{MFA_CHALLENGE_MINUTES, create_mfa_challenge};
+use crate::utils::refresh_token::{
+    RefreshTokenError, revoke_refresh_family, revoke_user_refresh_tokens, rotate_refresh_token,
+    store_refresh_token,
+};
+use crate::utils::revocation::revoke_claims;
+use crate::utils::role::default_user_roles;
+use crate::utils::scope::{default_user_scopes, resolve_client_scope, restrict_to_user};
+use crate::utils::session::{start_session, touch_session};
 
 #[derive(Debug, Deserialize, ToSchema)]
 #[schema(example = json!({
@@ -32,13 +50,14 @@ pub struct RegisterRequest {
     security(), // 빈 security - 인증 불필요
     request_body = RegisterRequest,
     responses(
-        (status = 200, description = "User registered successfully", body = String, example = json!("User registered successfully")),
+        (status = 200, description = "User registered unverified, a verification link was emailed", body = String, example = json!("User registered successfully, check your email to verify your address")),
         (status = 400, description = "Email already exists", body = String, example = json!("Email already exists")),
         (status = 500, description = "Failed to hash password or register user", body = String, example = json!("Failed to hash password"))
     )
 )]
 pub async fn register_user(
     db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
     form: web::Json<RegisterRequest>,
 ) -> HttpResponse {
     let collection = db.collection::<User>("users");
@@ -56,28 +75,49 @@ pub async fn register_user(
         }
     };
 
-    let new_user = User {
+    let mut new_user = User {
         id: None,
         username: form.username.clone(),
         email: form.email.clone(),
         password: password_hash,
-        refresh_token: None,
+        email_verified: false,
+        scopes: default_user_scopes(),
+        roles: default_user_roles(),
+        token_version: 0,
+        totp_secret: None,
+        totp_pending_secret: None,
+        totp_last_step: 0,
+        recovery_codes: Vec::new(),
+        webauthn_credentials: Vec::new(),
     };
 
-    match collection.insert_one(new_user).await {
-        Ok(_) => HttpResponse::Ok().body("User registered successfully"),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to register user"),
+    match collection.insert_one(&new_user).await {
+        Ok(result) => new_user.id = result.inserted_id.as_object_id(),
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to register user"),
     }
+
+    // the account exists either way; the link can be sent again through /verify-email/resend
+    if let Err(e) = send_verification_email(&db, mailer.get_ref(), &new_user).await {
+        eprintln!("Failed to send verification email: {}", e);
+    }
+
+    HttpResponse::Ok().body("User registered successfully, check your email to verify your address")
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
 #[schema(example = json!({
     "email": "admin@djamware.com",
-    "password": "mypassword"
+    "password": "mypassword",
+    "device_name": "Work laptop",
+    "scope": "profile sessions"
 }))]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub device_name: Option<String>, // shown in the session list
+    #[serde(default)]
+    pub scope: Option<String>, // space-delimited, defaults to every scope the user may grant
 }
 
 //-----------------------------------------
@@ -90,49 +130,161 @@ pub struct LoginRequest {
     request_body = LoginRequest,
     responses(
         (status = 200, description = "Login successful, returns access and refresh tokens", body = TokenResponse),
+        (status = 202, description = "Password accepted, the user has two-factor authentication: exchange `mfa_token` and a code at /login/mfa, or a WebAuthn assertion at /webauthn/login", body = MfaChallengeResponse),
+        (status = 400, description = "invalid_scope - unknown scope or none the user may grant", body = OAuthError),
         (status = 401, description = "Invalid credentials"),
+        (status = 403, description = "Email address not verified (when REQUIRE_EMAIL_VERIFICATION is set)"),
+        (status = 429, description = "Account locked after too many failed logins, see Retry-After", body = String),
         (status = 500, description = "Database or token generation error")
     )
 )]
 #[post("/login")]
 pub async fn login(
     db: web::Data<Database>,
+    req: HttpRequest,
     credentials: web::Json<LoginRequest>,
 ) -> Result<HttpResponse, Error> {
     let collection = db.collection::<User>("users");
 
+    // a locked email doesn't even get its password checked, account or not
+    if let Some(seconds) = locked_for(&db, &credentials.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+    {
+        return Ok(locked_response(seconds));
+    }
+
     let user = collection
         .find_one(doc! { "email": &credentials.email })
         .await
-        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
-        .ok_or_else(|| ErrorUnauthorized("Invalide credentials in database"))?;
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
 
-    // validate passsword (user argon2 vefificatiaon)
-    if !verify_password(&user.password, &credentials.password)
-        .map_err(|_| ErrorUnauthorized("Invalid credentials in verify_password"))?
-    {
-        return Err(ErrorUnauthorized("Invalid credentials in verify_password"));
+    // validate passsword (user argon2 vefificatiaon), unknown emails fail the same way
+    let valid = verify_account_password(
+        user.as_ref().map(|user| user.password.as_str()),
+        &credentials.password,
+    );
+    let Some(user) = user.filter(|_| valid) else {
+        record_login_failure(&db, &credentials.email)
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+        return Err(ErrorUnauthorized("Invalid credentials"));
+    };
+
+    if email_verification_required() && !user.email_verified {
+        return Err(ErrorForbidden("Email address not verified"));
+    }
+
+    let scope = resolve_client_scope(credentials.scope.as_deref(), None)?;
+    let scope = restrict_to_user(&scope, &user.scopes)?;
+
+    let user_id = user
+        .id
+        .ok_or_else(|| ErrorInternalServerError("User has no id"))?;
+
+    // the password alone isn't enough: hand out a challenge to exchange at /login/mfa
+    if user.mfa_enabled() {
+        let mfa_token =
+            create_mfa_challenge(&db, user_id, &scope, credentials.device_name.as_deref())
+                .await
+                .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+        return Ok(HttpResponse::Accepted().json(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            mfa_methods: user.mfa_methods(),
+            expires_in: MFA_CHALLENGE_MINUTES * 60,
+        }));
     }
 
-    let access_token = create_jwt(&user.email, 15, "access")
+    // every login is an independent session
+    let session_id = start_session(&db, &req, user_id, None, credentials.device_name.as_deref())
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Failed to start session: {}", e)))?;
+
+    let tokens = issue_token_pair(&db, &user, None, Some(&scope), &session_id).await?;
+    record_login_success(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Issue an access/refresh token pair for a user session and save the refresh token.
+/// Token lifetimes come from the OAuth2 client when there is one. First-party access tokens
+/// carry the user's current roles; tokens issued to a client never do, so authorizing a
+/// client doesn't hand it the user's admin rights.
+/// The refresh token joins the session's refresh token family (see utils::refresh_token).
+pub async fn issue_token_pair(
+    db: &Database,
+    user: &User,
+    client: Option<&Client>,
+    scope: Option<&str>,
+    session_id: &str,
+) -> Result<TokenResponse, Error> {
+    let user_id = user
+        .id
+        .ok_or_else(|| ErrorInternalServerError("User has no id"))?;
+    let client_id = client.map(|c| c.client_id.clone());
+    let (access_ttl, refresh_ttl) = match client {
+        Some(client) => (client.access_token_ttl(), client.refresh_token_ttl()),
+        None => (
+            Duration::minutes(ACCESS_TOKEN_MINUTES),
+            Duration::minutes(REFRESH_TOKEN_MINUTES),
+        ),
+    };
+
+    let access_claims = Claims {
+        client_id: client_id.clone(),
+        scope: scope.map(str::to_owned),
+        sid: Some(session_id.to_string()),
+        roles: match client {
+            Some(_) => Vec::new(),
+            None => user.roles.clone(),
+        },
+        ver: Some(user.token_version),
+        ..Claims::new(&user.subject(), access_ttl, "access")
+    };
+    let refresh_claims = Claims {
+        client_id,
+        scope: scope.map(str::to_owned),
+        sid: Some(session_id.to_string()),
+        ver: Some(user.token_version),
+        ..Claims::new(&user.subject(), refresh_ttl, "refresh")
+    };
+
+    let access_token = create_jwt(&access_claims)
         .map_err(|e| ErrorInternalServerError(format!("Token generation error: {}", e)))?;
 
-    let new_refresh_token = create_jwt(&user.email, 60 * 24 * 7, "refresh")
+    let new_refresh_token = create_jwt(&refresh_claims)
         .map_err(|e| ErrorInternalServerError(format!("Token generation error: {}", e)))?;
 
     // Save refresh token
-    collection
-        .update_one(
-            doc! { "email": &user.email },
-            doc! { "$set": { "refresh_token": &new_refresh_token }},
-        )
+    store_refresh_token(db, &refresh_claims, session_id, user_id)
         .await
         .map_err(|e| ErrorInternalServerError(format!("Failed to save refresh token: {}", e)))?;
+    touch_session(db, session_id, &refresh_claims)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Failed to update session: {}", e)))?;
 
-    Ok(HttpResponse::Ok().json(TokenResponse {
+    Ok(TokenResponse {
         access_token,
         refresh_token: new_refresh_token,
-    }))
+        scope: scope.map(str::to_owned),
+    })
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "mfa_required": true,
+    "mfa_token": "challenge_token_here",
+    "mfa_methods": ["totp", "webauthn", "recovery_code"],
+    "expires_in": 300
+}))]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub mfa_methods: Vec<String>, // second factors the user has
+    pub expires_in: i64,          // seconds
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -143,11 +295,14 @@ pub struct RefreshRequest {
 #[derive(Debug, Serialize, ToSchema)]
 #[schema(example = json!({
     "access_token": "your_access_token_here",
-    "refresh_token": "your_refresh_token_here"
+    "refresh_token": "your_refresh_token_here",
+    "scope": "openid profile email offline_access sessions"
 }))]
 pub struct TokenResponse {
-    access_token: String,
-    refresh_token: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // granted scopes
 }
 
 //-----------------------------------------
@@ -168,55 +323,64 @@ pub async fn refresh_token(
     db: web::Data<Database>,
     payload: web::Json<RefreshRequest>,
 ) -> Result<HttpResponse, Error> {
-    let secret = std::env::var("JWT_SECRET").unwrap();
-
-    let decoded_data: TokenData<Claims> = decode::<Claims>(
-        &payload.refresh_token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    )
-    .map_err(|_| ErrorUnauthorized("Invalid refresh token"))?;
+    let claims = verify_jwt(&payload.refresh_token)
+        .map_err(|e| ErrorUnauthorized(format!("Invalid refresh token: {}", e)))?;
 
-    if decoded_data.claims.token_type != "refresh" {
+    if claims.token_type != "refresh" {
         return Err(ErrorUnauthorized("Not a refresh token"));
     }
 
-    let collection = db.collection::<User>("users");
-    let user = collection
-        .find_one(doc! { "email": &decoded_data.claims.sub })
+    // OAuth2 clients must authenticate at the token endpoint to refresh
+    if claims.client_id.is_some() {
+        return Err(ErrorUnauthorized(
+            "Refresh tokens issued to an OAuth2 client must be refreshed at /token",
+        ));
+    }
+
+    let user = db
+        .collection::<User>("users")
+        .find_one(subject_filter(&claims.sub))
         .await
         .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
         .ok_or_else(|| ErrorUnauthorized("Invalid credentials"))?;
 
-    if user.refresh_token.as_deref() != Some(&payload.refresh_token) {
-        return Err(ErrorUnauthorized("Refresh token mismatch"));
+    // signed out by a password change
+    if !user.token_is_current(&claims) {
+        return Err(ErrorUnauthorized("Invalid refresh token"));
     }
 
-    let new_access_token = create_jwt(&user.email, 15, "access")
-        .map_err(|e| ErrorInternalServerError(format!("Token generation error: {}", e)))?;
-
-    let new_refresh_token = create_jwt(&user.email, 60 * 24 * 7, "refresh")
-        .map_err(|e| ErrorInternalServerError(format!("Token generation error: {}", e)))?;
+    // the user may have lost scopes since the login; tokens from before scopes existed get the defaults
+    let scope = match claims.scope.as_deref() {
+        Some(scope) => scope.to_string(),
+        None => resolve_client_scope(None, None)?,
+    };
+    let scope = restrict_to_user(&scope, &user.scopes)?;
 
-    // update stored refresh token
-    collection
-        .update_one(
-            doc! { "email": &user.email },
-            doc! { "$set": { "refresh_token": &new_refresh_token }},
-        )
+    let rotated = rotate_refresh_token(&db, &claims)
         .await
-        .map_err(|e| ErrorInternalServerError(format!("Failed to save refresh token: {}", e)))?;
+        .map_err(|e| match e {
+            RefreshTokenError::Invalid => ErrorUnauthorized("Invalid refresh token"),
+            RefreshTokenError::Reused => {
+                ErrorUnauthorized("Refresh token reuse detected, please log in again")
+            }
+            RefreshTokenError::Database(e) => {
+                ErrorInternalServerError(format!("Database error: {}", e))
+            }
+        })?;
 
-    Ok(HttpResponse::Ok().json(TokenResponse {
-        access_token: new_access_token,
-        refresh_token: new_refresh_token,
-    }))
+    // issue a child token in the same family
+    let tokens = issue_token_pair(&db, &user, None, Some(&scope), &rotated.family_id).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
 }
 
 #[derive(Serialize, ToSchema)]
-#[schema(example = json!({"email": "user@example.com", "message": "Your are authorized. This is a protected route"}))]
+#[schema(example = json!({"email": "user@example.com", "username": "djamware", "mfa_enabled": true, "recovery_codes_remaining": 8, "message": "Your are authorized. This is a protected route"}))]
 pub struct ProfileResponse {
     email: String,
+    username: String,
+    mfa_enabled: bool,
+    recovery_codes_remaining: usize,
     message: String,
 }
 
@@ -227,23 +391,28 @@ pub struct ProfileResponse {
     path = "/api/profile",
     operation_id = "profile",
     security(
-        ("bearer_auth" = [])
+        ("bearer_auth" = ["profile"])
     ),
     responses(
         (status = 200, description = "Profile data", body = ProfileResponse),
-        (status = 401, description = "Unauthorized - Missing or invalid token")
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - insufficient_scope")
     )
 )]
 // Protected route
-pub async fn get_profile(req: HttpRequest) -> HttpResponse {
-    if let Some(claims) = req.extensions().get::<Claims>() {
-        HttpResponse::Ok().json(ProfileResponse {
-            email: claims.sub.clone(),
-            message: "Your are authorized. This is a protected route".to_string(),
-        })
-    } else {
-        HttpResponse::Unauthorized().body("Unauthorized")
-    }
+pub async fn get_profile(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, Error> {
+    let user = user.load_user(&db).await?;
+
+    Ok(HttpResponse::Ok().json(ProfileResponse {
+        mfa_enabled: user.mfa_enabled(),
+        recovery_codes_remaining: user.recovery_codes.len(),
+        email: user.email,
+        username: user.username,
+        message: "Your are authorized. This is a protected route".to_string(),
+    }))
 }
 
 //-----------------------------------------
@@ -257,39 +426,35 @@ pub async fn get_profile(req: HttpRequest) -> HttpResponse {
     ),
     responses(
         (status = 200, description = "Logged out successfully", body = String, example = json!("Logged out successfully")),
-        (status = 401, description = "Unauthorized - Missing or invalid token"),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
         (status = 500, description = "Internal server error")
     )
 )]
 #[post("/logout")]
-pub async fn logout(db: web::Data<Database>, req: HttpRequest) -> Result<HttpResponse, Error> {
-    let user_email = get_email_from_request(&req)?;
+pub async fn logout(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, Error> {
     println!(
-        "-> handlers/auth.rs - logout - user_email: {:?}",
-        user_email
+        "-> handlers/auth.rs - logout - user_id: {:?}",
+        user.user_id()
     );
 
-    let collection = db.collection::<User>("users");
-    collection
-        .update_one(
-            doc! { "email": &user_email },
-            doc! { "$unset": { "refresh_token": "" }},
-        )
+    match user.claims.sid.as_deref() {
+        // end only the session the token belongs to
+        Some(session_id) => revoke_refresh_family(&db, session_id).await,
+        // tokens issued before sessions existed: end all of them
+        None => match user.load_user(&db).await?.id {
+            Some(user_id) => revoke_user_refresh_tokens(&db, user_id).await,
+            None => Ok(()),
+        },
+    }
+    .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+
+    // the access token used to log out is no longer active either
+    revoke_claims(&db, &user.claims)
         .await
-        .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+        .map_err(|e| ErrorInternalServerError(format!("Failed to revoke token: {}", e)))?;
 
     Ok(HttpResponse::Ok().body("Logged out successfully"))
 }
-
-fn get_email_from_request(req: &HttpRequest) -> Result<String, Error> {
-    let token = req
-        .headers()
-        .get(AUTHORIZATION)
-        .and_then(|h| h.to_str().ok())
-        .and_then(|auth_header| auth_header.strip_prefix("Bearer "))
-        .ok_or_else(|| {
-            actix_web::error::ErrorUnauthorized("Missingor invalid authorization header")
-        })?;
-
-    extract_email_from_jwt(token)
-}
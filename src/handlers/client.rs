@@ -0,0 +1,446 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, CACHE_CONTROL, PRAGMA};
+use actix_web::{HttpRequest, HttpResponse, delete, get, post, put, web};
+use chrono::Utc;
+use mongodb::Database;
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use std::env;
+use url::Url;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::config::base_url;
+use crate::models::client::{Client, SUPPORTED_GRANT_TYPES};
+use crate::utils::hash::hash_password;
+use crate::utils::oauth::OAuthError;
+use crate::utils::scope::SUPPORTED_SCOPES;
+use crate::utils::token::{generate_token, hash_token};
+
+/// RFC 7591 client metadata
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "client_name": "My App",
+    "redirect_uris": ["http://localhost:3000/callback"],
+    "grant_types": ["authorization_code", "refresh_token"],
+    "response_types": ["code"],
+    "scope": "openid profile email",
+    "token_endpoint_auth_method": "client_secret_basic",
+    "access_token_lifetime": 900,
+    "refresh_token_lifetime": 604800
+}))]
+pub struct ClientMetadata {
+    pub client_id: Option<String>, // must match the path on RFC 7592 updates
+    pub client_name: Option<String>,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Option<Vec<String>>,
+    pub response_types: Option<Vec<String>>,
+    pub scope: Option<String>,
+    pub token_endpoint_auth_method: Option<String>,
+    pub access_token_lifetime: Option<i64>,
+    pub refresh_token_lifetime: Option<i64>,
+}
+
+/// RFC 7591 section 3.2.1 / RFC 7592 section 3 client information response
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "client_id": "8b0c3f0e-2c1d-4a53-9f9f-0c6a1d1c2b3a",
+    "client_secret": "generated_client_secret",
+    "client_id_issued_at": 1748600000,
+    "client_secret_expires_at": 0,
+    "registration_access_token": "generated_registration_access_token",
+    "registration_client_uri": "http://localhost:8080/register-client/8b0c3f0e-2c1d-4a53-9f9f-0c6a1d1c2b3a",
+    "client_name": "My App",
+    "redirect_uris": ["http://localhost:3000/callback"],
+    "grant_types": ["authorization_code", "refresh_token"],
+    "response_types": ["code"],
+    "scope": "openid profile email",
+    "token_endpoint_auth_method": "client_secret_basic"
+}))]
+pub struct ClientInformation {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub client_id_issued_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration_access_token: Option<String>,
+    pub registration_client_uri: String,
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub response_types: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    pub token_endpoint_auth_method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token_lifetime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token_lifetime: Option<i64>,
+}
+
+impl ClientInformation {
+    fn from_client(client: &Client) -> Self {
+        ClientInformation {
+            client_id: client.client_id.clone(),
+            client_secret: None,
+            client_id_issued_at: client.client_id_issued_at,
+            client_secret_expires_at: client.is_confidential().then_some(0),
+            registration_access_token: None,
+            registration_client_uri: format!("{}/register-client/{}", base_url(), client.client_id),
+            client_name: client.client_name.clone(),
+            redirect_uris: client.redirect_uris.clone(),
+            grant_types: client.grant_types.clone(),
+            response_types: client.response_types.clone(),
+            scope: client.scope.clone(),
+            token_endpoint_auth_method: client.token_endpoint_auth_method.clone(),
+            access_token_lifetime: client.access_token_lifetime,
+            refresh_token_lifetime: client.refresh_token_lifetime,
+        }
+    }
+}
+
+/// Checked client metadata, with defaults applied
+struct ValidatedMetadata {
+    client_name: String,
+    redirect_uris: Vec<String>,
+    grant_types: Vec<String>,
+    response_types: Vec<String>,
+    scope: Option<String>,
+    token_endpoint_auth_method: String,
+    access_token_lifetime: Option<i64>,
+    refresh_token_lifetime: Option<i64>,
+}
+
+fn invalid_metadata(description: &str) -> OAuthError {
+    OAuthError::new(
+        StatusCode::BAD_REQUEST,
+        "invalid_client_metadata",
+        description,
+    )
+}
+
+/// RFC 7591 section 2 metadata validation
+fn validate_metadata(metadata: ClientMetadata) -> Result<ValidatedMetadata, OAuthError> {
+    let grant_types = metadata
+        .grant_types
+        .unwrap_or_else(|| vec!["authorization_code".to_string()]);
+    if grant_types.is_empty()
+        || grant_types
+            .iter()
+            .any(|g| !SUPPORTED_GRANT_TYPES.contains(&g.as_str()))
+    {
+        return Err(invalid_metadata("Unsupported grant_types"));
+    }
+    let uses_code = grant_types.iter().any(|g| g == "authorization_code");
+
+    let response_types = metadata.response_types.unwrap_or_else(|| {
+        if uses_code {
+            vec!["code".to_string()]
+        } else {
+            Vec::new()
+        }
+    });
+    if response_types.iter().any(|r| r != "code") || uses_code == response_types.is_empty() {
+        return Err(invalid_metadata(
+            "response_types must be [\"code\"] exactly when authorization_code is used",
+        ));
+    }
+
+    if uses_code && metadata.redirect_uris.is_empty() {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_redirect_uri",
+            "redirect_uris is required for the authorization_code grant",
+        ));
+    }
+    // absolute URIs without a fragment (RFC 6749 section 3.1.2)
+    if metadata
+        .redirect_uris
+        .iter()
+        .any(|uri| Url::parse(uri).map_or(true, |url| url.fragment().is_some()))
+    {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_redirect_uri",
+            "redirect_uris must be absolute URIs without a fragment",
+        ));
+    }
+
+    let token_endpoint_auth_method = metadata
+        .token_endpoint_auth_method
+        .unwrap_or_else(|| "client_secret_basic".to_string());
+    if !["client_secret_basic", "client_secret_post", "none"]
+        .contains(&token_endpoint_auth_method.as_str())
+    {
+        return Err(invalid_metadata("Unsupported token_endpoint_auth_method"));
+    }
+    if token_endpoint_auth_method == "none" && grant_types.iter().any(|g| g == "client_credentials")
+    {
+        return Err(invalid_metadata(
+            "Public clients can't use the client_credentials grant",
+        ));
+    }
+
+    if metadata
+        .scope
+        .as_deref()
+        .is_some_and(|scope| scope.split(' ').any(|s| !SUPPORTED_SCOPES.contains(&s)))
+    {
+        return Err(invalid_metadata("Unsupported scope"));
+    }
+
+    let positive = |lifetime: Option<i64>| lifetime.is_none_or(|seconds| seconds > 0);
+    if !positive(metadata.access_token_lifetime) || !positive(metadata.refresh_token_lifetime) {
+        return Err(invalid_metadata("Token lifetimes must be positive"));
+    }
+
+    Ok(ValidatedMetadata {
+        client_name: metadata.client_name.unwrap_or_default(),
+        redirect_uris: metadata.redirect_uris,
+        grant_types,
+        response_types,
+        scope: metadata.scope,
+        token_endpoint_auth_method,
+        access_token_lifetime: metadata.access_token_lifetime,
+        refresh_token_lifetime: metadata.refresh_token_lifetime,
+    })
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+fn invalid_token() -> OAuthError {
+    OAuthError::new(
+        StatusCode::UNAUTHORIZED,
+        "invalid_token",
+        "Invalid registration access token",
+    )
+}
+
+/// Load the client addressed by an RFC 7592 request, checking its registration access token
+async fn find_registered_client(
+    db: &Database,
+    req: &HttpRequest,
+    client_id: &str,
+) -> Result<Client, OAuthError> {
+    let token = bearer_token(req).ok_or_else(invalid_token)?;
+
+    db.collection::<Client>("clients")
+        .find_one(doc! {
+            "client_id": client_id,
+            "registration_access_token_hash": hash_token(token),
+        })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(invalid_token)
+}
+
+fn no_store(mut response: HttpResponse) -> HttpResponse {
+    let headers = response.headers_mut();
+    headers.insert(CACHE_CONTROL, "no-store".parse().unwrap());
+    headers.insert(PRAGMA, "no-cache".parse().unwrap());
+    response
+}
+
+//-----------------------------------------
+/// Register a new OAuth2 client (RFC 7591)
+#[utoipa::path(
+    post,
+    path = "/register-client",
+    operation_id = "register_client",
+    tag = "Client Registration",
+    security(
+        (),
+        ("bearer_auth" = []) // initial access token when CLIENT_REGISTRATION_TOKEN is set
+    ),
+    request_body = ClientMetadata,
+    responses(
+        (status = 201, description = "Client registered", body = ClientInformation),
+        (status = 400, description = "invalid_client_metadata or invalid_redirect_uri", body = OAuthError),
+        (status = 401, description = "Missing or invalid initial access token", body = OAuthError)
+    )
+)]
+#[post("/register-client")]
+pub async fn register_client(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    metadata: web::Json<ClientMetadata>,
+) -> Result<HttpResponse, OAuthError> {
+    // open registration unless an initial access token is configured (RFC 7591 section 3)
+    if let Ok(initial_access_token) = env::var("CLIENT_REGISTRATION_TOKEN")
+        // hashed first so the comparison time says nothing about the configured token
+        && bearer_token(&req).map(hash_token) != Some(hash_token(&initial_access_token))
+    {
+        return Err(OAuthError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "Invalid initial access token",
+        ));
+    }
+
+    let metadata = validate_metadata(metadata.into_inner())?;
+
+    let client_secret = (metadata.token_endpoint_auth_method != "none").then(generate_token);
+    let client_secret_hash = client_secret
+        .as_deref()
+        .map(hash_password)
+        .transpose()
+        .map_err(|_| OAuthError::server_error("Failed to hash client secret"))?;
+    let registration_access_token = generate_token();
+
+    let client = Client {
+        id: None,
+        client_id: Uuid::new_v4().to_string(),
+        client_name: metadata.client_name,
+        redirect_uris: metadata.redirect_uris,
+        client_secret_hash,
+        grant_types: metadata.grant_types,
+        response_types: metadata.response_types,
+        scope: metadata.scope,
+        token_endpoint_auth_method: metadata.token_endpoint_auth_method,
+        access_token_lifetime: metadata.access_token_lifetime,
+        refresh_token_lifetime: metadata.refresh_token_lifetime,
+        registration_access_token_hash: Some(hash_token(&registration_access_token)),
+        client_id_issued_at: Utc::now().timestamp(),
+    };
+
+    db.collection::<Client>("clients")
+        .insert_one(&client)
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Failed to register client: {}", e)))?;
+
+    let information = ClientInformation {
+        client_secret,
+        registration_access_token: Some(registration_access_token),
+        ..ClientInformation::from_client(&client)
+    };
+    Ok(no_store(HttpResponse::Created().json(information)))
+}
+
+//-----------------------------------------
+/// Read a registered client's configuration (RFC 7592)
+#[utoipa::path(
+    get,
+    path = "/register-client/{client_id}",
+    operation_id = "read_client",
+    tag = "Client Registration",
+    security(
+        ("bearer_auth" = []) // registration access token
+    ),
+    params(("client_id" = String, Path, description = "Client identifier")),
+    responses(
+        (status = 200, description = "Client configuration", body = ClientInformation),
+        (status = 401, description = "Invalid registration access token", body = OAuthError)
+    )
+)]
+#[get("/register-client/{client_id}")]
+pub async fn read_client(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = find_registered_client(&db, &req, &path).await?;
+
+    Ok(no_store(
+        HttpResponse::Ok().json(ClientInformation::from_client(&client)),
+    ))
+}
+
+//-----------------------------------------
+/// Replace a registered client's configuration (RFC 7592)
+#[utoipa::path(
+    put,
+    path = "/register-client/{client_id}",
+    operation_id = "update_client",
+    tag = "Client Registration",
+    security(
+        ("bearer_auth" = []) // registration access token
+    ),
+    params(("client_id" = String, Path, description = "Client identifier")),
+    request_body = ClientMetadata,
+    responses(
+        (status = 200, description = "Client updated", body = ClientInformation),
+        (status = 400, description = "invalid_client_metadata or invalid_redirect_uri", body = OAuthError),
+        (status = 401, description = "Invalid registration access token", body = OAuthError)
+    )
+)]
+#[put("/register-client/{client_id}")]
+pub async fn update_client(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    metadata: web::Json<ClientMetadata>,
+) -> Result<HttpResponse, OAuthError> {
+    let mut client = find_registered_client(&db, &req, &path).await?;
+
+    if metadata.client_id.as_deref() != Some(client.client_id.as_str()) {
+        return Err(OAuthError::invalid_request(
+            "client_id in the body must match the registered client",
+        ));
+    }
+
+    let metadata = validate_metadata(metadata.into_inner())?;
+    // switching between public and confidential would need a new secret: register a new client instead
+    if (metadata.token_endpoint_auth_method == "none") == client.is_confidential() {
+        return Err(invalid_metadata(
+            "token_endpoint_auth_method can't switch between public and confidential",
+        ));
+    }
+
+    client.client_name = metadata.client_name;
+    client.redirect_uris = metadata.redirect_uris;
+    client.grant_types = metadata.grant_types;
+    client.response_types = metadata.response_types;
+    client.scope = metadata.scope;
+    client.token_endpoint_auth_method = metadata.token_endpoint_auth_method;
+    client.access_token_lifetime = metadata.access_token_lifetime;
+    client.refresh_token_lifetime = metadata.refresh_token_lifetime;
+
+    db.collection::<Client>("clients")
+        .replace_one(doc! { "client_id": &client.client_id }, &client)
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Failed to update client: {}", e)))?;
+
+    Ok(no_store(
+        HttpResponse::Ok().json(ClientInformation::from_client(&client)),
+    ))
+}
+
+//-----------------------------------------
+/// Delete a registered client (RFC 7592)
+#[utoipa::path(
+    delete,
+    path = "/register-client/{client_id}",
+    operation_id = "delete_client",
+    tag = "Client Registration",
+    security(
+        ("bearer_auth" = []) // registration access token
+    ),
+    params(("client_id" = String, Path, description = "Client identifier")),
+    responses(
+        (status = 204, description = "Client deleted"),
+        (status = 401, description = "Invalid registration access token", body = OAuthError)
+    )
+)]
+#[delete("/register-client/{client_id}")]
+pub async fn delete_client(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = find_registered_client(&db, &req, &path).await?;
+
+    db.collection::<Client>("clients")
+        .delete_one(doc! { "client_id": &client.client_id })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Failed to delete client: {}", e)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
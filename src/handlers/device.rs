@@ -0,0 +1,575 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CACHE_CONTROL, HeaderValue, PRAGMA, RETRY_AFTER};
+use actix_web::{HttpRequest, HttpResponse, get, post, web};
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::{base_url, email_verification_required};
+use crate::handlers::auth::issue_token_pair;
+use crate::handlers::oauth::{
+    OAuthTokenResponse, TokenRequest, authenticate_client, html_escape, issue_id_token, required,
+};
+use crate::models::client::{Client, DEVICE_CODE_GRANT_TYPE};
+use crate::models::device_code::DeviceCode;
+use crate::models::user::User;
+use crate::utils::hash::verify_account_password;
+use crate::utils::lockout::{
+    ACCOUNT_LOCKED_MESSAGE, locked_for, record_login_failure, record_login_success,
+    record_user_code_failure, user_code_locked_for,
+};
+use crate::utils::mfa::verify_second_factor;
+use crate::utils::oauth::OAuthError;
+use crate::utils::scope::{has_scope, resolve_client_scope, restrict_to_user};
+use crate::utils::session::start_session;
+use crate::utils::token::{generate_token, generate_user_code, hash_token, normalize_user_code};
+
+pub const DEVICE_CODE_SECONDS: i64 = 600;
+pub const DEVICE_POLL_INTERVAL_SECONDS: i64 = 5;
+
+const USER_CODES_LOCKED_MESSAGE: &str = "Too many unknown codes, try again later";
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "client_id": "my-cli",
+    "scope": "openid profile"
+}))]
+pub struct DeviceAuthorizationRequest {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// RFC 8628 section 3.2 device authorization response
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "device_code": "your_device_code_here",
+    "user_code": "WDJB-MJHT",
+    "verification_uri": "http://localhost:8080/device",
+    "verification_uri_complete": "http://localhost:8080/device?user_code=WDJB-MJHT",
+    "expires_in": 600,
+    "interval": 5
+}))]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+//-----------------------------------------
+/// OAuth2 device authorization endpoint (RFC 8628)
+#[utoipa::path(
+    post,
+    path = "/device_authorization",
+    operation_id = "device_authorization",
+    tag = "OAuth2",
+    security(), // client authentication: HTTP Basic or client_id/client_secret
+    request_body(content = DeviceAuthorizationRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Device and user codes issued", body = DeviceAuthorizationResponse),
+        (status = 400, description = "unauthorized_client or invalid_scope", body = OAuthError),
+        (status = 401, description = "invalid_client", body = OAuthError)
+    )
+)]
+#[post("/device_authorization")]
+pub async fn device_authorization(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    form: web::Form<DeviceAuthorizationRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = authenticate_client(
+        &db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    if !client.allows_grant(DEVICE_CODE_GRANT_TYPE) {
+        return Err(OAuthError::unauthorized_client(
+            "Client is not allowed to use the device_code grant",
+        ));
+    }
+
+    let scope = resolve_client_scope(form.scope.as_deref(), client.scope.as_deref())?;
+
+    let device_code = generate_token();
+    let user_code = generate_user_code();
+    let expires_at = DateTime::from_millis(
+        (Utc::now() + Duration::seconds(DEVICE_CODE_SECONDS)).timestamp_millis(),
+    );
+
+    db.collection::<DeviceCode>("device_codes")
+        .insert_one(DeviceCode {
+            id: None,
+            device_code_hash: hash_token(&device_code),
+            user_code: normalize_user_code(&user_code),
+            client_id: client.client_id.clone(),
+            scope: Some(scope),
+            status: "pending".to_string(),
+            user_email: None,
+            auth_time: None,
+            interval: DEVICE_POLL_INTERVAL_SECONDS,
+            last_polled_at: None,
+            expires_at,
+        })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Failed to save device code: {}", e)))?;
+
+    let verification_uri = format!("{}/device", base_url());
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(DeviceAuthorizationResponse {
+            device_code,
+            verification_uri_complete: format!("{}?user_code={}", verification_uri, user_code),
+            verification_uri,
+            user_code,
+            expires_in: DEVICE_CODE_SECONDS,
+            interval: DEVICE_POLL_INTERVAL_SECONDS,
+        }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeviceVerificationQuery {
+    pub user_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeviceVerificationForm {
+    pub user_code: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub mfa_code: String, // TOTP or recovery code, when the user has two-factor authentication
+    pub decision: Option<String>, // "approve" or "deny"
+}
+
+//-----------------------------------------
+/// Device verification page - the user enters the code shown on the device
+#[utoipa::path(
+    get,
+    path = "/device",
+    operation_id = "device_verification",
+    tag = "OAuth2",
+    security(), // 빈 security - 인증 불필요
+    params(DeviceVerificationQuery),
+    responses(
+        (status = 200, description = "HTML form for the user code and credentials", content_type = "text/html")
+    )
+)]
+#[get("/device")]
+pub async fn device_verification(query: web::Query<DeviceVerificationQuery>) -> HttpResponse {
+    html(
+        StatusCode::OK,
+        render_device_page(query.user_code.as_deref().unwrap_or(""), None),
+    )
+}
+
+//-----------------------------------------
+/// Device verification page - the user signs in and approves or denies the device
+#[utoipa::path(
+    post,
+    path = "/device",
+    operation_id = "device_verification_submit",
+    tag = "OAuth2",
+    security(), // 빈 security - 인증 불필요
+    request_body(content = DeviceVerificationForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Device approved or denied", content_type = "text/html"),
+        (status = 400, description = "Unknown or expired user code, form is shown again", content_type = "text/html"),
+        (status = 401, description = "Invalid credentials, form is shown again", content_type = "text/html"),
+        (status = 403, description = "Email address not verified, or the user may not grant any of the requested scopes", content_type = "text/html"),
+        (status = 429, description = "Too many unknown user codes from this address, or too many failed logins for this email; see Retry-After", content_type = "text/html")
+    )
+)]
+#[post("/device")]
+pub async fn device_verification_submit(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    form: web::Form<DeviceVerificationForm>,
+) -> HttpResponse {
+    // RFC 8628 section 5.1: user codes are short, so guessing them is throttled per address
+    let client_ip = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    match user_code_locked_for(&db, &client_ip).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            return too_many_requests(
+                seconds,
+                render_device_page(&form.user_code, Some(USER_CODES_LOCKED_MESSAGE)),
+            );
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    }
+
+    let collection = db.collection::<DeviceCode>("device_codes");
+    let user_code = normalize_user_code(&form.user_code);
+    let pending = doc! {
+        "user_code": &user_code,
+        "status": "pending",
+        "expires_at": { "$gt": DateTime::now() },
+    };
+
+    let device_code = match collection.find_one(pending.clone()).await {
+        Ok(device_code) => device_code,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+    let Some(device_code) = device_code else {
+        if let Err(e) = record_user_code_failure(&db, &client_ip).await {
+            eprintln!("Failed to record user code failure: {}", e);
+        }
+        return html(
+            StatusCode::BAD_REQUEST,
+            render_device_page(&form.user_code, Some("Unknown or expired code")),
+        );
+    };
+
+    // a locked email doesn't even get its password checked, account or not
+    match locked_for(&db, &form.email).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            return too_many_requests(
+                seconds,
+                render_device_page(&form.user_code, Some(ACCOUNT_LOCKED_MESSAGE)),
+            );
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    }
+
+    let user = match db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &form.email })
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    // unknown emails fail the same way as wrong passwords
+    let valid = verify_account_password(
+        user.as_ref().map(|user| user.password.as_str()),
+        &form.password,
+    );
+    let Some(user) = user.filter(|_| valid) else {
+        if let Err(e) = record_login_failure(&db, &form.email).await {
+            eprintln!("Failed to record login failure: {}", e);
+        }
+        return html(
+            StatusCode::UNAUTHORIZED,
+            render_device_page(&form.user_code, Some("Invalid credentials")),
+        );
+    };
+
+    if email_verification_required() && !user.email_verified {
+        return html(
+            StatusCode::FORBIDDEN,
+            render_device_page(
+                &form.user_code,
+                Some("Verify your email address before signing in"),
+            ),
+        );
+    }
+
+    // the form has no passkey ceremony, so it asks for a code from users with an authenticator app
+    if user.totp_enabled()
+        && !verify_second_factor(&db, &user, &form.mfa_code)
+            .await
+            .unwrap_or(false)
+    {
+        if let Err(e) = record_login_failure(&db, &user.email).await {
+            eprintln!("Failed to record login failure: {}", e);
+        }
+        return html(
+            StatusCode::UNAUTHORIZED,
+            render_device_page(&form.user_code, Some("Invalid authenticator code")),
+        );
+    }
+
+    if let Err(e) = record_login_success(&db, &user.email).await {
+        eprintln!("Failed to reset failed logins: {}", e);
+    }
+
+    // denying takes the same sign-in as approving, so a stranger can't cancel it
+    if form.decision.as_deref() == Some("deny") {
+        return match collection
+            .update_one(pending, doc! { "$set": { "status": "denied" }})
+            .await
+        {
+            Ok(_) => html(
+                StatusCode::OK,
+                render_result_page("Access denied. You can close this window."),
+            ),
+            Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+        };
+    }
+
+    // the user grants what they may out of what the client requested
+    let Ok(scope) = restrict_to_user(device_code.scope.as_deref().unwrap_or(""), &user.scopes)
+    else {
+        return html(
+            StatusCode::FORBIDDEN,
+            render_device_page(
+                &form.user_code,
+                Some("You can't grant any of the requested scopes"),
+            ),
+        );
+    };
+
+    let client_name = db
+        .collection::<Client>("clients")
+        .find_one(doc! { "client_id": &device_code.client_id })
+        .await
+        .ok()
+        .flatten()
+        .map(|client| client.client_name)
+        .unwrap_or(device_code.client_id);
+
+    match collection
+        .update_one(
+            pending,
+            doc! { "$set": {
+                "status": "approved",
+                "user_email": &user.email,
+                "auth_time": Utc::now().timestamp(),
+                "scope": scope,
+            }},
+        )
+        .await
+    {
+        Ok(_) => html(
+            StatusCode::OK,
+            render_result_page(&format!(
+                "{} is now signed in. You can close this window.",
+                client_name
+            )),
+        ),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+fn device_error(error: &'static str, description: &str) -> OAuthError {
+    OAuthError::new(StatusCode::BAD_REQUEST, error, description)
+}
+
+/// Token endpoint polling for `urn:ietf:params:oauth:grant-type:device_code` (RFC 8628 section 3.4)
+pub async fn device_code_grant(
+    db: &Database,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let device_code = required(&form.device_code, "device_code")?;
+
+    let client = authenticate_client(
+        db,
+        req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    if !client.allows_grant(DEVICE_CODE_GRANT_TYPE) {
+        return Err(OAuthError::unauthorized_client(
+            "Client is not allowed to use the device_code grant",
+        ));
+    }
+
+    let collection = db.collection::<DeviceCode>("device_codes");
+    let device_code_hash = hash_token(device_code);
+    let now = DateTime::now();
+
+    // record this poll and look at the previous one
+    let pending = collection
+        .find_one_and_update(
+            doc! { "device_code_hash": &device_code_hash },
+            doc! { "$set": { "last_polled_at": now }},
+        )
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(|| OAuthError::invalid_grant("Invalid device_code"))?;
+
+    if pending.client_id != client.client_id {
+        return Err(OAuthError::invalid_grant(
+            "device_code was issued to another client",
+        ));
+    }
+
+    if pending.expires_at <= now {
+        return Err(device_error(
+            "expired_token",
+            "The device_code has expired, start a new device authorization",
+        ));
+    }
+
+    match pending.status.as_str() {
+        "pending" => {
+            let too_fast = pending.last_polled_at.is_some_and(|last| {
+                now.timestamp_millis() - last.timestamp_millis() < pending.interval * 1000
+            });
+            if too_fast {
+                collection
+                    .update_one(
+                        doc! { "device_code_hash": &device_code_hash },
+                        doc! { "$inc": { "interval": DEVICE_POLL_INTERVAL_SECONDS }},
+                    )
+                    .await
+                    .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?;
+                return Err(device_error(
+                    "slow_down",
+                    &format!(
+                        "Polling too fast, wait at least {} seconds between requests",
+                        pending.interval + DEVICE_POLL_INTERVAL_SECONDS
+                    ),
+                ));
+            }
+            Err(device_error(
+                "authorization_pending",
+                "The user has not approved the device yet",
+            ))
+        }
+        "denied" => {
+            collection
+                .delete_one(doc! { "device_code_hash": &device_code_hash })
+                .await
+                .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?;
+            Err(device_error("access_denied", "The user denied the request"))
+        }
+        _ => {
+            // approved device codes are single-use: delete on redemption
+            let approved = collection
+                .find_one_and_delete(doc! {
+                    "device_code_hash": &device_code_hash,
+                    "status": "approved",
+                })
+                .await
+                .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+                .ok_or_else(|| OAuthError::invalid_grant("Invalid device_code"))?;
+            let user_email = approved
+                .user_email
+                .ok_or_else(|| OAuthError::invalid_grant("Invalid device_code"))?;
+
+            let user = db
+                .collection::<User>("users")
+                .find_one(doc! { "email": &user_email })
+                .await
+                .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+                .ok_or_else(|| OAuthError::invalid_grant("User no longer exists"))?;
+
+            let user_id = user
+                .id
+                .ok_or_else(|| OAuthError::server_error("User has no id"))?;
+            let session_id = start_session(
+                db,
+                req,
+                user_id,
+                Some(&client.client_id),
+                Some(&client.client_name),
+            )
+            .await
+            .map_err(|e| OAuthError::server_error(format!("Failed to start session: {}", e)))?;
+
+            let tokens = issue_token_pair(
+                db,
+                &user,
+                Some(&client),
+                approved.scope.as_deref(),
+                &session_id,
+            )
+            .await
+            .map_err(|e| OAuthError::server_error(e.to_string()))?;
+
+            let id_token = if has_scope(approved.scope.as_deref(), "openid") {
+                Some(issue_id_token(
+                    &client,
+                    &user,
+                    approved.auth_time.unwrap_or_default(),
+                    None,
+                    &tokens.access_token,
+                )?)
+            } else {
+                None
+            };
+
+            Ok(OAuthTokenResponse {
+                access_token: tokens.access_token,
+                token_type: "Bearer".to_string(),
+                expires_in: client.access_token_ttl().num_seconds(),
+                refresh_token: Some(tokens.refresh_token),
+                scope: approved.scope,
+                id_token,
+            })
+        }
+    }
+}
+
+fn html(status: StatusCode, body: String) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((PRAGMA, "no-cache"))
+        .content_type("text/html; charset=utf-8")
+        .body(body)
+}
+
+fn too_many_requests(seconds: i64, body: String) -> HttpResponse {
+    let mut response = html(StatusCode::TOO_MANY_REQUESTS, body);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+fn render_device_page(user_code: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!(r#"<p style="color:red">{}</p>"#, html_escape(e)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><meta charset="utf-8"><title>Connect a device</title></head>
+  <body>
+    <h1>Connect a device</h1>
+    {error}
+    <p>Enter the code shown on your device and sign in to allow or deny it.</p>
+    <form method="post" action="/device">
+      <label>Code <input type="text" name="user_code" value="{user_code}" autocomplete="off" required></label><br>
+      <label>Email <input type="email" name="email" required></label><br>
+      <label>Password <input type="password" name="password" required></label><br>
+      <label>Authenticator code (if enabled) <input type="text" name="mfa_code" inputmode="numeric" autocomplete="one-time-code"></label><br>
+      <button type="submit" name="decision" value="approve">Sign in and allow</button>
+      <button type="submit" name="decision" value="deny">Sign in and deny</button>
+    </form>
+  </body>
+</html>"#,
+        error = error,
+        user_code = html_escape(user_code),
+    )
+}
+
+fn render_result_page(message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><meta charset="utf-8"><title>Connect a device</title></head>
+  <body><h1>Connect a device</h1><p>{}</p></body>
+</html>"#,
+        html_escape(message)
+    )
+}
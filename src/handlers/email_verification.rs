@@ -0,0 +1,183 @@
+use actix_web::{HttpResponse, get, post, web};
+use mongodb::Database;
+use mongodb::bson::doc;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::user::User;
+use crate::utils::email_verification::{send_verification_email, verify_email_token};
+use crate::utils::mailer::Mailer;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+//-----------------------------------------
+/// Verify an email address with the link sent at registration
+#[utoipa::path(
+    get,
+    path = "/verify-email",
+    operation_id = "verify_email",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    params(VerifyEmailQuery),
+    responses(
+        (status = 200, description = "Email address verified", body = String, example = json!("Email address verified")),
+        (status = 400, description = "Invalid, expired or already used link", body = String)
+    )
+)]
+#[get("/verify-email")]
+pub async fn verify_email(
+    db: web::Data<Database>,
+    query: web::Query<VerifyEmailQuery>,
+) -> HttpResponse {
+    match verify_email_token(&db, &query.token).await {
+        Ok(true) => HttpResponse::Ok().body("Email address verified"),
+        Ok(false) => HttpResponse::BadRequest().body("Invalid or expired verification link"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "email": "admin@djamware.com" }))]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+//-----------------------------------------
+/// Send a new verification link
+#[utoipa::path(
+    post,
+    path = "/verify-email/resend",
+    operation_id = "resend_verification_email",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 200, description = "Always returned, whether or not the address belongs to an unverified account", body = String)
+    )
+)]
+#[post("/verify-email/resend")]
+pub async fn resend_verification_email(
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<ResendVerificationRequest>,
+) -> HttpResponse {
+    // the response never reveals whether the account exists
+    let user = db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &payload.email, "email_verified": { "$ne": true } })
+        .await;
+    match user {
+        Ok(Some(user)) => {
+            if let Err(e) = send_verification_email(&db, mailer.get_ref(), &user).await {
+                eprintln!("Failed to send verification email: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Database error: {}", e),
+    }
+
+    HttpResponse::Ok()
+        .body("If the address belongs to an unverified account, a new verification link was sent")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test, web};
+    use mongodb::Database;
+    use serde_json::json;
+    use std::env;
+    use std::sync::{Arc, Once};
+
+    use super::verify_email;
+    use crate::config;
+    use crate::handlers::auth::{login, register_user};
+    use crate::utils::mailer::{Mailer, MemoryMailer};
+
+    static ENV: Once = Once::new();
+
+    async fn test_db() -> Database {
+        ENV.call_once(|| {
+            // SAFETY: set once, before any test of this module reads the environment
+            unsafe {
+                env::set_var("JWT_SECRET", "test-secret");
+                env::set_var("REQUIRE_EMAIL_VERIFICATION", "true");
+            }
+        });
+        config::test_db().await
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGODB_URI"]
+    async fn registration_link_verifies_the_address_once() {
+        let db = test_db().await;
+        let mailer = Arc::new(MemoryMailer::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .route("/register", web::post().to(register_user))
+                .service(verify_email)
+                .service(login),
+        )
+        .await;
+
+        let register = test::TestRequest::post()
+            .uri("/register")
+            .set_json(json!({
+                "username": "alice",
+                "email": "alice@example.com",
+                "password": "correct horse",
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, register).await.status(),
+            StatusCode::OK
+        );
+
+        let login_request = || {
+            test::TestRequest::post()
+                .uri("/login")
+                .set_json(json!({ "email": "alice@example.com", "password": "correct horse" }))
+                .to_request()
+        };
+        // REQUIRE_EMAIL_VERIFICATION keeps the unverified account out
+        assert_eq!(
+            test::call_service(&app, login_request()).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "alice@example.com");
+        let link = sent[0]
+            .body
+            .split_whitespace()
+            .find(|word| word.contains("/verify-email?token="))
+            .expect("no verification link in the email");
+        let path = &link[link.find("/verify-email").unwrap()..];
+
+        let verify = test::TestRequest::get().uri(path).to_request();
+        assert_eq!(
+            test::call_service(&app, verify).await.status(),
+            StatusCode::OK
+        );
+        // the link works once
+        let verify_again = test::TestRequest::get().uri(path).to_request();
+        assert_eq!(
+            test::call_service(&app, verify_again).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(
+            test::call_service(&app, login_request()).await.status(),
+            StatusCode::OK
+        );
+
+        db.drop().await.unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::{Error, HttpRequest, HttpResponse, post, web};
+use chrono::Utc;
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::totp_issuer;
+use crate::handlers::auth::issue_token_pair;
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::mfa_challenge::MfaChallenge;
+use crate::models::user::User;
+use crate::utils::audit::record_security_event;
+use crate::utils::lockout::{
+    locked_for, locked_response, record_login_failure, record_login_success,
+};
+use crate::utils::mfa::{MFA_MAX_ATTEMPTS, new_recovery_codes, verify_second_factor};
+use crate::utils::session::start_session;
+use crate::utils::token::hash_token;
+use crate::utils::totp::{generate_secret, provisioning_uri, verify_code};
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+    "provisioning_uri": "otpauth://totp/Rust%20OAuth2%20API%3Aadmin%40djamware%2Ecom?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=Rust%20OAuth2%20API&algorithm=SHA1&digits=6&period=30"
+}))]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,           // base32, for manual entry
+    pub provisioning_uri: String, // otpauth:// URI, shown as a QR code
+}
+
+//-----------------------------------------
+/// Start TOTP enrollment of the signed-in user
+#[utoipa::path(
+    post,
+    path = "/api/mfa/totp",
+    operation_id = "enroll_totp",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "New secret; two-factor authentication is on once /api/mfa/totp/confirm accepts a code", body = TotpEnrollmentResponse),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String),
+        (status = 409, description = "TOTP is already enabled", body = String)
+    )
+)]
+// Protected route
+pub async fn enroll_totp(db: web::Data<Database>, user: AuthenticatedUser) -> HttpResponse {
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change two-factor authentication");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    if account.totp_secret.is_some() {
+        return HttpResponse::Conflict().body("TOTP is already enabled");
+    }
+
+    // a new enrollment replaces an unconfirmed one
+    let secret = generate_secret();
+    if let Err(e) = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id },
+            doc! { "$set": { "totp_pending_secret": &secret } },
+        )
+        .await
+    {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+
+    HttpResponse::Ok().json(TotpEnrollmentResponse {
+        provisioning_uri: provisioning_uri(&secret, &account.email, &totp_issuer()),
+        secret,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "code": "123456" }))]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+//-----------------------------------------
+/// Confirm TOTP enrollment with the first code from the authenticator app and get recovery codes
+#[utoipa::path(
+    post,
+    path = "/api/mfa/totp/confirm",
+    operation_id = "confirm_totp",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "TOTP enabled, returns new recovery codes", body = RecoveryCodesResponse),
+        (status = 400, description = "No enrollment in progress, or wrong code", body = String),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String),
+        (status = 500, description = "Failed to hash recovery codes or update user", body = String)
+    )
+)]
+// Protected route
+pub async fn confirm_totp(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+    payload: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change two-factor authentication");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let Some(secret) = account.totp_pending_secret.as_deref() else {
+        return HttpResponse::BadRequest().body("No two-factor enrollment in progress");
+    };
+    let Some(step) = verify_code(secret, &payload.code, Utc::now().timestamp()) else {
+        return HttpResponse::BadRequest().body("Invalid code");
+    };
+
+    let (recovery_codes, recovery_code_hashes) = match new_recovery_codes() {
+        Ok(codes) => codes,
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("Failed to hash recovery codes");
+        }
+    };
+
+    // the confirming code counts as used
+    match db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id, "totp_pending_secret": secret },
+            doc! {
+                "$set": {
+                    "totp_secret": secret,
+                    "totp_last_step": step,
+                    "recovery_codes": recovery_code_hashes,
+                },
+                "$unset": { "totp_pending_secret": "" },
+            },
+        )
+        .await
+    {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => return HttpResponse::BadRequest().body("No two-factor enrollment in progress"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Database update failed: {}", e));
+        }
+    }
+    record_security_event(
+        &db,
+        "mfa_enabled",
+        Some(&account.email),
+        None,
+        "TOTP two-factor authentication enabled",
+    )
+    .await;
+
+    HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes })
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({ "recovery_codes": ["k7m2p-x9qrt", "a3bcd-ef4gh"] }))]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>, // shown once, each works a single time in place of a TOTP code
+}
+
+//-----------------------------------------
+/// Replace the recovery codes of the signed-in user
+#[utoipa::path(
+    post,
+    path = "/api/mfa/recovery-codes",
+    operation_id = "regenerate_recovery_codes",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "New recovery codes, the previous ones no longer work", body = RecoveryCodesResponse),
+        (status = 400, description = "Two-factor authentication is not enabled, or wrong code", body = String),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String)
+    )
+)]
+// Protected route
+pub async fn regenerate_recovery_codes(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+    payload: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change two-factor authentication");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    if !account.mfa_enabled() {
+        return HttpResponse::BadRequest().body("Two-factor authentication is not enabled");
+    }
+
+    // a stolen access token alone can't mint new codes
+    match verify_second_factor(&db, &account, &payload.code).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().body("Invalid code"),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    }
+
+    let (recovery_codes, recovery_code_hashes) = match new_recovery_codes() {
+        Ok(codes) => codes,
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("Failed to hash recovery codes");
+        }
+    };
+    if let Err(e) = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id },
+            doc! { "$set": { "recovery_codes": recovery_code_hashes } },
+        )
+        .await
+    {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+    record_security_event(
+        &db,
+        "recovery_codes_regenerated",
+        Some(&account.email),
+        None,
+        "MFA recovery codes replaced",
+    )
+    .await;
+
+    HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "mfa_token": "challenge_token_from_login", "code": "123456" }))]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    pub code: String, // TOTP code or recovery code
+}
+
+//-----------------------------------------
+/// Second login step: exchange the MFA challenge and a TOTP or recovery code for tokens
+#[utoipa::path(
+    post,
+    path = "/login/mfa",
+    operation_id = "login_mfa",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = MfaLoginRequest,
+    responses(
+        (status = 200, description = "Login successful, returns access and refresh tokens", body = TokenResponse),
+        (status = 401, description = "Invalid or expired challenge, or wrong or already used code"),
+        (status = 429, description = "Account locked after too many failed logins, see Retry-After", body = String),
+        (status = 500, description = "Database or token generation error")
+    )
+)]
+#[post("/login/mfa")]
+pub async fn login_mfa(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    payload: web::Json<MfaLoginRequest>,
+) -> Result<HttpResponse, Error> {
+    let challenges = db.collection::<MfaChallenge>("mfa_challenges");
+    let challenge = challenges
+        .find_one(doc! {
+            "token_hash": hash_token(&payload.mfa_token),
+            "expires_at": { "$gt": DateTime::now() },
+        })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorUnauthorized("Invalid or expired MFA challenge"))?;
+
+    let user = db
+        .collection::<User>("users")
+        .find_one(doc! { "_id": challenge.user_id })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorUnauthorized("Invalid or expired MFA challenge"))?;
+
+    if let Some(seconds) = locked_for(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+    {
+        return Ok(locked_response(seconds));
+    }
+
+    let valid = verify_second_factor(&db, &user, &payload.code)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+    if !valid {
+        // a challenge only gets a few guesses
+        let update = if challenge.attempts + 1 >= MFA_MAX_ATTEMPTS {
+            challenges
+                .delete_one(doc! { "_id": challenge.id })
+                .await
+                .map(|_| ())
+        } else {
+            challenges
+                .update_one(
+                    doc! { "_id": challenge.id },
+                    doc! { "$inc": { "attempts": 1 } },
+                )
+                .await
+                .map(|_| ())
+        };
+        update.map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+        // new challenges don't buy more guesses: wrong codes count towards the lockout too
+        record_login_failure(&db, &user.email)
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+        return Err(ErrorUnauthorized("Invalid code"));
+    }
+
+    // challenges are single-use
+    let deleted = challenges
+        .delete_one(doc! { "_id": challenge.id })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+    if deleted.deleted_count == 0 {
+        return Err(ErrorUnauthorized("Invalid or expired MFA challenge"));
+    }
+
+    let session_id = start_session(
+        &db,
+        &req,
+        challenge.user_id,
+        None,
+        challenge.device_name.as_deref(),
+    )
+    .await
+    .map_err(|e| ErrorInternalServerError(format!("Failed to start session: {}", e)))?;
+
+    let tokens = issue_token_pair(&db, &user, None, Some(&challenge.scope), &session_id).await?;
+    record_login_success(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
@@ -1 +1,11 @@
+pub mod admin;
 pub mod auth;
+pub mod client;
+pub mod device;
+pub mod email_verification;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod password;
+pub mod session;
+pub mod webauthn;
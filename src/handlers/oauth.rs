@@ -0,0 +1,1073 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CACHE_CONTROL, LOCATION, PRAGMA, RETRY_AFTER};
+use actix_web::{HttpRequest, HttpResponse, get, post, web};
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::{email_verification_required, issuer};
+use crate::handlers::auth::issue_token_pair;
+use crate::handlers::device::device_code_grant;
+use crate::models::authorization_code::AuthorizationCode;
+use crate::models::client::{Client, DEVICE_CODE_GRANT_TYPE};
+use crate::models::user::{User, subject_filter};
+use crate::utils::hash::{verify_account_password, verify_password};
+use crate::utils::jwt::{
+    ACCESS_TOKEN_MINUTES, Claims, ID_TOKEN_MINUTES, IdTokenClaims, ValidationPolicy, at_hash,
+    create_jwt, validate_jwt, verify_jwt,
+};
+use crate::utils::lockout::{
+    ACCOUNT_LOCKED_MESSAGE, locked_for, record_login_failure, record_login_success,
+};
+use crate::utils::mfa::verify_second_factor;
+use crate::utils::oauth::{OAuthError, basic_client_credentials, verify_pkce_s256};
+use crate::utils::refresh_token::{
+    RefreshTokenError, is_refresh_token_active, revoke_refresh_token, rotate_refresh_token,
+};
+use crate::utils::revocation::{is_revoked, revoke_token};
+use crate::utils::scope::{has_scope, resolve_client_scope, restrict_to_user};
+use crate::utils::session::start_session;
+use crate::utils::token::{generate_token, hash_token};
+use crate::utils::token_version::is_outdated;
+
+pub const AUTHORIZATION_CODE_SECONDS: i64 = 60;
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorizeRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>, // OpenID Connect, echoed in the ID token
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuthorizeLoginForm {
+    #[serde(flatten)]
+    pub request: AuthorizeRequest,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub mfa_code: String, // TOTP or recovery code, when the user has two-factor authentication
+    pub decision: Option<String>, // "approve" or "deny"
+}
+
+/// Why an authorization request was rejected
+enum AuthorizeError {
+    /// client_id or redirect_uri can't be trusted: show the error instead of redirecting
+    Page(String),
+    /// redirect back to the client with an RFC 6749 section 4.1.2.1 error
+    Redirect {
+        redirect_uri: String,
+        error: &'static str,
+        description: String,
+        state: Option<String>,
+    },
+}
+
+impl AuthorizeError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            AuthorizeError::Page(message) => HttpResponse::BadRequest()
+                .content_type("text/html; charset=utf-8")
+                .body(render_error_page(&message)),
+            AuthorizeError::Redirect {
+                redirect_uri,
+                error,
+                description,
+                state,
+            } => {
+                let mut params = vec![("error", error), ("error_description", &description)];
+                if let Some(state) = state.as_deref() {
+                    params.push(("state", state));
+                }
+                redirect_with(&redirect_uri, &params)
+            }
+        }
+    }
+}
+
+/// Validate client_id, redirect_uri, scope and the PKCE parameters of an authorization request.
+/// Returns the client, the redirect URI and the scope the client may be granted.
+async fn validate_authorize_request(
+    db: &Database,
+    req: &AuthorizeRequest,
+) -> Result<(Client, String, String), AuthorizeError> {
+    let client_id = req
+        .client_id
+        .as_deref()
+        .ok_or_else(|| AuthorizeError::Page("Missing client_id".to_string()))?;
+
+    let client = db
+        .collection::<Client>("clients")
+        .find_one(doc! { "client_id": client_id })
+        .await
+        .map_err(|e| AuthorizeError::Page(format!("Database error: {}", e)))?
+        .ok_or_else(|| AuthorizeError::Page("Unknown client".to_string()))?;
+
+    let redirect_uri = client
+        .resolve_redirect_uri(req.redirect_uri.as_deref())
+        .filter(|uri| Url::parse(uri).is_ok())
+        .ok_or_else(|| AuthorizeError::Page("Invalid redirect_uri".to_string()))?;
+
+    let redirect_error = |error: &'static str, description: &str| AuthorizeError::Redirect {
+        redirect_uri: redirect_uri.clone(),
+        error,
+        description: description.to_string(),
+        state: req.state.clone(),
+    };
+
+    if req.response_type.as_deref() != Some("code") {
+        return Err(redirect_error(
+            "unsupported_response_type",
+            "Only response_type=code is supported",
+        ));
+    }
+
+    if req.code_challenge.as_deref().is_none_or(str::is_empty) {
+        return Err(redirect_error(
+            "invalid_request",
+            "code_challenge is required (PKCE)",
+        ));
+    }
+
+    // RFC 7636 defaults to "plain" when the method is omitted, which we don't allow
+    if req.code_challenge_method.as_deref() != Some("S256") {
+        return Err(redirect_error(
+            "invalid_request",
+            "code_challenge_method must be S256",
+        ));
+    }
+
+    let scope =
+        resolve_client_scope(req.scope.as_deref(), client.scope.as_deref()).map_err(|e| {
+            redirect_error(
+                "invalid_scope",
+                e.error_description.as_deref().unwrap_or(""),
+            )
+        })?;
+
+    Ok((client, redirect_uri, scope))
+}
+
+//-----------------------------------------
+/// OAuth2 authorization endpoint - shows the login form
+#[utoipa::path(
+    get,
+    path = "/authorize",
+    operation_id = "authorize",
+    tag = "OAuth2",
+    security(), // 빈 security - 인증 불필요
+    params(AuthorizeRequest),
+    responses(
+        (status = 200, description = "HTML login form", content_type = "text/html"),
+        (status = 302, description = "Redirect to the client with an error"),
+        (status = 400, description = "Unknown client or invalid redirect_uri", content_type = "text/html")
+    )
+)]
+#[get("/authorize")]
+pub async fn authorize(
+    db: web::Data<Database>,
+    query: web::Query<AuthorizeRequest>,
+) -> HttpResponse {
+    match validate_authorize_request(&db, &query).await {
+        Ok((client, _, _)) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(render_login_page(&query, &client, None)),
+        Err(e) => e.into_response(),
+    }
+}
+
+//-----------------------------------------
+/// OAuth2 authorization endpoint - authenticates the user and issues an authorization code
+#[utoipa::path(
+    post,
+    path = "/authorize",
+    operation_id = "authorize_login",
+    tag = "OAuth2",
+    security(), // 빈 security - 인증 불필요
+    request_body(content = AuthorizeLoginForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 302, description = "Redirect to the client with `code` and `state`, or an error"),
+        (status = 400, description = "Unknown client or invalid redirect_uri", content_type = "text/html"),
+        (status = 401, description = "Invalid credentials, login form is shown again", content_type = "text/html"),
+        (status = 403, description = "Email address not verified, login form is shown again", content_type = "text/html")
+    )
+)]
+#[post("/authorize")]
+pub async fn authorize_login(
+    db: web::Data<Database>,
+    form: web::Form<AuthorizeLoginForm>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let (client, redirect_uri, scope) = match validate_authorize_request(&db, &form.request).await {
+        Ok(validated) => validated,
+        Err(e) => return e.into_response(),
+    };
+
+    if form.decision.as_deref() == Some("deny") {
+        return AuthorizeError::Redirect {
+            redirect_uri,
+            error: "access_denied",
+            description: "The user denied the request".to_string(),
+            state: form.request.state,
+        }
+        .into_response();
+    }
+
+    // a locked email doesn't even get its password checked, account or not
+    match locked_for(&db, &form.email).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            return HttpResponse::TooManyRequests()
+                .content_type("text/html; charset=utf-8")
+                .insert_header((RETRY_AFTER, seconds.to_string()))
+                .body(render_login_page(
+                    &form.request,
+                    &client,
+                    Some(ACCOUNT_LOCKED_MESSAGE),
+                ));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    }
+
+    let user = match db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &form.email })
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let invalid_credentials = || {
+        HttpResponse::Unauthorized()
+            .content_type("text/html; charset=utf-8")
+            .body(render_login_page(
+                &form.request,
+                &client,
+                Some("Invalid credentials"),
+            ))
+    };
+    // unknown emails fail the same way as wrong passwords
+    let valid = verify_account_password(
+        user.as_ref().map(|user| user.password.as_str()),
+        &form.password,
+    );
+    let Some(user) = user.filter(|_| valid) else {
+        if let Err(e) = record_login_failure(&db, &form.email).await {
+            eprintln!("Failed to record login failure: {}", e);
+        }
+        return invalid_credentials();
+    };
+
+    if email_verification_required() && !user.email_verified {
+        return HttpResponse::Forbidden()
+            .content_type("text/html; charset=utf-8")
+            .body(render_login_page(
+                &form.request,
+                &client,
+                Some("Verify your email address before signing in"),
+            ));
+    }
+
+    // the form has no passkey ceremony, so it asks for a code from users with an authenticator app
+    if user.totp_enabled()
+        && !verify_second_factor(&db, &user, &form.mfa_code)
+            .await
+            .unwrap_or(false)
+    {
+        if let Err(e) = record_login_failure(&db, &user.email).await {
+            eprintln!("Failed to record login failure: {}", e);
+        }
+        return HttpResponse::Unauthorized()
+            .content_type("text/html; charset=utf-8")
+            .body(render_login_page(
+                &form.request,
+                &client,
+                Some("Invalid authenticator code"),
+            ));
+    }
+
+    let scope = match restrict_to_user(&scope, &user.scopes) {
+        Ok(scope) => scope,
+        Err(e) => {
+            return AuthorizeError::Redirect {
+                redirect_uri,
+                error: e.error,
+                description: e.error_description.unwrap_or_default(),
+                state: form.request.state,
+            }
+            .into_response();
+        }
+    };
+
+    let code = generate_token();
+    let expires_at = DateTime::from_millis(
+        (Utc::now() + Duration::seconds(AUTHORIZATION_CODE_SECONDS)).timestamp_millis(),
+    );
+    let authorization_code = AuthorizationCode {
+        id: None,
+        code_hash: hash_token(&code),
+        client_id: client.client_id.clone(),
+        user_email: user.email.clone(),
+        redirect_uri: redirect_uri.clone(),
+        scope: Some(scope),
+        code_challenge: form.request.code_challenge.clone().unwrap_or_default(),
+        code_challenge_method: "S256".to_string(),
+        nonce: form.request.nonce.clone(),
+        auth_time: Utc::now().timestamp(),
+        expires_at,
+    };
+
+    if let Err(e) = db
+        .collection::<AuthorizationCode>("authorization_codes")
+        .insert_one(authorization_code)
+        .await
+    {
+        return AuthorizeError::Redirect {
+            redirect_uri,
+            error: "server_error",
+            description: format!("Failed to save authorization code: {}", e),
+            state: form.request.state,
+        }
+        .into_response();
+    }
+
+    if let Err(e) = record_login_success(&db, &user.email).await {
+        eprintln!("Failed to reset failed logins: {}", e);
+    }
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = form.request.state.as_deref() {
+        params.push(("state", state));
+    }
+    redirect_with(&redirect_uri, &params)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "grant_type": "authorization_code",
+    "code": "authorization_code_here",
+    "redirect_uri": "http://localhost:3000/callback",
+    "client_id": "my-client",
+    "code_verifier": "code_verifier_here"
+}))]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub device_code: Option<String>, // RFC 8628 device_code grant
+}
+
+/// RFC 6749 section 5.1 access token response
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "access_token": "your_access_token_here",
+    "token_type": "Bearer",
+    "expires_in": 900,
+    "refresh_token": "your_refresh_token_here",
+    "scope": "openid profile",
+    "id_token": "your_id_token_here"
+}))]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>, // OpenID Connect, only with the openid scope
+}
+
+//-----------------------------------------
+/// OAuth2 token endpoint
+#[utoipa::path(
+    post,
+    path = "/token",
+    operation_id = "token",
+    tag = "OAuth2",
+    security(), // 빈 security - 인증 불필요
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Tokens issued", body = OAuthTokenResponse),
+        (status = 400, description = "invalid_request, invalid_grant, unsupported_grant_type, ...", body = OAuthError),
+        (status = 401, description = "invalid_client", body = OAuthError)
+    )
+)]
+#[post("/token")]
+pub async fn token(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    form: web::Form<TokenRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let response = match form.grant_type.as_str() {
+        "authorization_code" => authorization_code_grant(&db, &req, &form).await?,
+        "refresh_token" => refresh_token_grant(&db, &req, &form).await?,
+        "client_credentials" => client_credentials_grant(&db, &req, &form).await?,
+        DEVICE_CODE_GRANT_TYPE => device_code_grant(&db, &req, &form).await?,
+        other => {
+            return Err(OAuthError::unsupported_grant_type(format!(
+                "Unsupported grant_type: {}",
+                other
+            )));
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((PRAGMA, "no-cache"))
+        .json(response))
+}
+
+/// Authenticate the calling client with HTTP Basic (client_secret_basic) or form
+/// parameters (client_secret_post). Public clients only identify themselves by client_id.
+pub async fn authenticate_client(
+    db: &Database,
+    req: &HttpRequest,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<Client, OAuthError> {
+    let (client_id, client_secret) = match basic_client_credentials(req) {
+        Some(_) if client_secret.is_some() => {
+            return Err(OAuthError::invalid_request(
+                "Use only one client authentication method",
+            ));
+        }
+        Some((basic_id, _)) if client_id.is_some_and(|id| id != basic_id) => {
+            return Err(OAuthError::invalid_client("client_id mismatch"));
+        }
+        Some((basic_id, basic_secret)) => (basic_id, Some(basic_secret)),
+        None => (
+            client_id
+                .map(str::to_owned)
+                .ok_or_else(|| OAuthError::invalid_client("Missing client credentials"))?,
+            client_secret.map(str::to_owned),
+        ),
+    };
+
+    let client = db
+        .collection::<Client>("clients")
+        .find_one(doc! { "client_id": &client_id })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(|| OAuthError::invalid_client("Client authentication failed"))?;
+
+    match (&client.client_secret_hash, client_secret) {
+        (Some(hash), Some(secret)) if verify_password(hash, &secret).unwrap_or(false) => Ok(client),
+        (None, None) => Ok(client),
+        _ => Err(OAuthError::invalid_client("Client authentication failed")),
+    }
+}
+
+async fn authorization_code_grant(
+    db: &Database,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let code = required(&form.code, "code")?;
+    let redirect_uri = required(&form.redirect_uri, "redirect_uri")?;
+    let code_verifier = required(&form.code_verifier, "code_verifier")?;
+
+    let client = authenticate_client(
+        db,
+        req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+    let client_id = client.client_id.as_str();
+
+    if !client.allows_grant("authorization_code") {
+        return Err(OAuthError::unauthorized_client(
+            "Client is not allowed to use the authorization_code grant",
+        ));
+    }
+
+    // codes are single-use: delete on first lookup
+    let authorization_code = db
+        .collection::<AuthorizationCode>("authorization_codes")
+        .find_one_and_delete(doc! { "code_hash": hash_token(code) })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .filter(|c| c.expires_at > DateTime::now())
+        .ok_or_else(|| OAuthError::invalid_grant("Authorization code is invalid or expired"))?;
+
+    if authorization_code.client_id != client_id {
+        return Err(OAuthError::invalid_grant(
+            "Authorization code was issued to another client",
+        ));
+    }
+
+    if authorization_code.redirect_uri != redirect_uri {
+        return Err(OAuthError::invalid_grant("redirect_uri mismatch"));
+    }
+
+    if !verify_pkce_s256(code_verifier, &authorization_code.code_challenge) {
+        return Err(OAuthError::invalid_grant("PKCE verification failed"));
+    }
+
+    let collection = db.collection::<User>("users");
+    let user = collection
+        .find_one(doc! { "email": &authorization_code.user_email })
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(|| OAuthError::invalid_grant("User no longer exists"))?;
+
+    let user_id = user
+        .id
+        .ok_or_else(|| OAuthError::server_error("User has no id"))?;
+    let session_id = start_session(
+        db,
+        req,
+        user_id,
+        Some(&client.client_id),
+        Some(&client.client_name),
+    )
+    .await
+    .map_err(|e| OAuthError::server_error(format!("Failed to start session: {}", e)))?;
+
+    let tokens = issue_token_pair(
+        db,
+        &user,
+        Some(&client),
+        authorization_code.scope.as_deref(),
+        &session_id,
+    )
+    .await
+    .map_err(|e| OAuthError::server_error(e.to_string()))?;
+
+    // OpenID Connect: an ID token is returned when the openid scope was granted
+    let id_token = if has_scope(authorization_code.scope.as_deref(), "openid") {
+        Some(issue_id_token(
+            &client,
+            &user,
+            authorization_code.auth_time,
+            authorization_code.nonce.clone(),
+            &tokens.access_token,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(OAuthTokenResponse {
+        access_token: tokens.access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: client.access_token_ttl().num_seconds(),
+        refresh_token: Some(tokens.refresh_token),
+        scope: authorization_code.scope,
+        id_token,
+    })
+}
+
+/// OpenID Connect ID token for the user who authorized the client
+pub fn issue_id_token(
+    client: &Client,
+    user: &User,
+    auth_time: i64,
+    nonce: Option<String>,
+    access_token: &str,
+) -> Result<String, OAuthError> {
+    let now = Utc::now().timestamp() as usize;
+    let id_claims = IdTokenClaims {
+        iss: issuer(),
+        sub: user.subject(),
+        aud: client.client_id.clone(),
+        exp: now + (ID_TOKEN_MINUTES * 60) as usize,
+        iat: now,
+        auth_time: auth_time as usize,
+        nonce,
+        at_hash: Some(at_hash(access_token)),
+    };
+    create_jwt(&id_claims)
+        .map_err(|e| OAuthError::server_error(format!("Token generation error: {}", e)))
+}
+
+async fn refresh_token_grant(
+    db: &Database,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let refresh_token = required(&form.refresh_token, "refresh_token")?;
+
+    let claims = verify_jwt(refresh_token)
+        .ok()
+        .filter(|claims| claims.token_type == "refresh")
+        .ok_or_else(|| OAuthError::invalid_grant("Invalid refresh token"))?;
+
+    // tokens from an OAuth2 client can only be refreshed by that (authenticated) client
+    let client = if let Some(token_client_id) = claims.client_id.as_deref() {
+        let client = authenticate_client(
+            db,
+            req,
+            form.client_id.as_deref().or(Some(token_client_id)),
+            form.client_secret.as_deref(),
+        )
+        .await?;
+        if client.client_id != token_client_id {
+            return Err(OAuthError::invalid_grant(
+                "Refresh token was issued to another client",
+            ));
+        }
+        if !client.allows_grant("refresh_token") {
+            return Err(OAuthError::unauthorized_client(
+                "Client is not allowed to use the refresh_token grant",
+            ));
+        }
+        Some(client)
+    } else if form.client_id.is_some() {
+        return Err(OAuthError::invalid_grant(
+            "Refresh token was issued to another client",
+        ));
+    } else {
+        None
+    };
+
+    // a narrower scope may be requested, never a broader one
+    let scope = match form.scope.as_deref() {
+        Some(requested) => {
+            let granted: Vec<&str> = claims.scope.as_deref().unwrap_or("").split(' ').collect();
+            if !requested.split(' ').all(|s| granted.contains(&s)) {
+                return Err(OAuthError::invalid_scope(
+                    "Requested scope exceeds the original grant",
+                ));
+            }
+            Some(requested.to_string())
+        }
+        None => claims.scope.clone(),
+    };
+
+    let user = db
+        .collection::<User>("users")
+        .find_one(subject_filter(&claims.sub))
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(|| OAuthError::invalid_grant("Invalid refresh token"))?;
+
+    // signed out by a password change
+    if !user.token_is_current(&claims) {
+        return Err(OAuthError::invalid_grant("Invalid refresh token"));
+    }
+
+    // the user may have lost scopes since the grant
+    let scope = scope
+        .map(|scope| restrict_to_user(&scope, &user.scopes))
+        .transpose()?;
+
+    let rotated = rotate_refresh_token(db, &claims)
+        .await
+        .map_err(|e| match e {
+            RefreshTokenError::Invalid => OAuthError::invalid_grant("Invalid refresh token"),
+            RefreshTokenError::Reused => OAuthError::invalid_grant(
+                "Refresh token reuse detected, all tokens of this grant were revoked",
+            ),
+            RefreshTokenError::Database(e) => {
+                OAuthError::server_error(format!("Database error: {}", e))
+            }
+        })?;
+
+    let tokens = issue_token_pair(
+        db,
+        &user,
+        client.as_ref(),
+        scope.as_deref(),
+        &rotated.family_id,
+    )
+    .await
+    .map_err(|e| OAuthError::server_error(e.to_string()))?;
+
+    Ok(OAuthTokenResponse {
+        access_token: tokens.access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: client
+            .as_ref()
+            .map(Client::access_token_ttl)
+            .unwrap_or_else(|| Duration::minutes(ACCESS_TOKEN_MINUTES))
+            .num_seconds(),
+        refresh_token: Some(tokens.refresh_token),
+        scope,
+        id_token: None,
+    })
+}
+
+async fn client_credentials_grant(
+    db: &Database,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> Result<OAuthTokenResponse, OAuthError> {
+    let client = authenticate_client(
+        db,
+        req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    if !client.is_confidential() || !client.allows_grant("client_credentials") {
+        return Err(OAuthError::unauthorized_client(
+            "Client is not allowed to use the client_credentials grant",
+        ));
+    }
+
+    let scope = resolve_client_scope(form.scope.as_deref(), client.scope.as_deref())?;
+
+    // the client acts on its own behalf: the subject is the client itself (no refresh token)
+    let claims = Claims {
+        client_id: Some(client.client_id.clone()),
+        scope: Some(scope),
+        sub_type: "client".to_string(),
+        ..Claims::new(&client.client_id, client.access_token_ttl(), "access")
+    };
+    let access_token = create_jwt(&claims)
+        .map_err(|e| OAuthError::server_error(format!("Token generation error: {}", e)))?;
+
+    Ok(OAuthTokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: client.access_token_ttl().num_seconds(),
+        refresh_token: None,
+        scope: claims.scope,
+        id_token: None,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "token": "token_to_check",
+    "token_type_hint": "access_token"
+}))]
+pub struct IntrospectionRequest {
+    pub token: String,
+    #[allow(dead_code)] // documented for RFC 7662, the type is read from the token itself
+    pub token_type_hint: Option<String>, // "access_token" or "refresh_token"
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// RFC 7662 section 2.2 introspection response
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[schema(example = json!({
+    "active": true,
+    "sub": "665f1c2e8b3e4a2d9c0b1a2f",
+    "username": "user@example.com",
+    "exp": 1678886400,
+    "scope": "openid profile",
+    "token_type": "Bearer",
+    "client_id": "my-client"
+}))]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+//-----------------------------------------
+/// OAuth2 token introspection (RFC 7662)
+#[utoipa::path(
+    post,
+    path = "/introspect",
+    operation_id = "introspect",
+    tag = "OAuth2",
+    security(), // client authentication: HTTP Basic or client_id/client_secret
+    request_body(content = IntrospectionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token state; `{\"active\": false}` for invalid, expired or revoked tokens", body = IntrospectionResponse),
+        (status = 401, description = "invalid_client", body = OAuthError)
+    )
+)]
+#[post("/introspect")]
+pub async fn introspect(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    form: web::Form<IntrospectionRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = authenticate_client(
+        &db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+    if !client.is_confidential() {
+        return Err(OAuthError::invalid_client(
+            "Public clients can't introspect tokens",
+        ));
+    }
+
+    let response = introspect_token(&db, &form.token)
+        .await?
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(response))
+}
+
+/// Introspection of an active token, None when it's invalid, expired or revoked
+async fn introspect_token(
+    db: &Database,
+    jwt: &str,
+) -> Result<Option<IntrospectionResponse>, OAuthError> {
+    // access and refresh tokens only: other signed tokens are never credentials
+    let policy = ValidationPolicy {
+        token_type: None,
+        ..ValidationPolicy::from_env()
+    };
+    let Ok(claims) = validate_jwt(jwt, &policy) else {
+        return Ok(None);
+    };
+    let token_type = match claims.token_type.as_str() {
+        "access" => "Bearer",
+        "refresh" => "refresh_token",
+        _ => return Ok(None),
+    };
+
+    if is_revoked(db, &claims)
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        || is_outdated(db, &claims)
+            .await
+            .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+    {
+        return Ok(None);
+    }
+
+    let mut username = None;
+    if claims.is_client() {
+        // the client may have been deleted since the token was issued
+        let client = db
+            .collection::<Client>("clients")
+            .find_one(doc! { "client_id": &claims.sub })
+            .await
+            .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?;
+        if client.is_none() {
+            return Ok(None);
+        }
+    } else {
+        let user = db
+            .collection::<User>("users")
+            .find_one(subject_filter(&claims.sub))
+            .await
+            .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?;
+        let Some(user) = user else {
+            return Ok(None);
+        };
+        username = Some(user.email);
+        // rotated, reused or logged-out refresh tokens
+        if claims.token_type == "refresh"
+            && !is_refresh_token_active(db, &claims)
+                .await
+                .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(IntrospectionResponse {
+        active: true,
+        username,
+        sub: Some(claims.sub),
+        exp: Some(claims.exp),
+        scope: claims.scope,
+        token_type: Some(token_type.to_string()),
+        client_id: claims.client_id,
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "token": "token_to_revoke",
+    "token_type_hint": "refresh_token"
+}))]
+pub struct RevocationRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>, // "access_token" or "refresh_token"
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+//-----------------------------------------
+/// OAuth2 token revocation (RFC 7009)
+#[utoipa::path(
+    post,
+    path = "/revoke",
+    operation_id = "revoke",
+    tag = "OAuth2",
+    security(), // client authentication: HTTP Basic or client_id/client_secret
+    request_body(content = RevocationRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token revoked, or it was already invalid"),
+        (status = 400, description = "unsupported_token_type or token issued to another client", body = OAuthError),
+        (status = 401, description = "invalid_client", body = OAuthError)
+    )
+)]
+#[post("/revoke")]
+pub async fn revoke(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    form: web::Form<RevocationRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = authenticate_client(
+        &db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    if let Some(hint) = form.token_type_hint.as_deref()
+        && hint != "access_token"
+        && hint != "refresh_token"
+    {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_token_type",
+            format!("Unsupported token_type_hint: {}", hint),
+        ));
+    }
+
+    // invalid or expired tokens need no revocation (RFC 7009 section 2.2)
+    let Ok(claims) = verify_jwt(&form.token) else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    if claims.client_id.as_deref() != Some(client.client_id.as_str()) {
+        return Err(OAuthError::unauthorized_client(
+            "Token was not issued to this client",
+        ));
+    }
+
+    // revoking a refresh token ends the whole grant it belongs to
+    if claims.token_type == "refresh" {
+        revoke_refresh_token(&db, &claims)
+            .await
+            .map_err(|e| OAuthError::server_error(format!("Database update failed: {}", e)))?;
+    }
+
+    revoke_token(&db, &form.token)
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Failed to revoke token: {}", e)))?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str, OAuthError> {
+    value
+        .as_deref()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| OAuthError::invalid_request(format!("Missing {}", name)))
+}
+
+fn redirect_with(redirect_uri: &str, params: &[(&str, &str)]) -> HttpResponse {
+    let Ok(mut url) = Url::parse(redirect_uri) else {
+        return HttpResponse::BadRequest().body("Invalid redirect_uri");
+    };
+    url.query_pairs_mut().extend_pairs(params);
+
+    HttpResponse::Found()
+        .insert_header((LOCATION, url.as_str()))
+        .finish()
+}
+
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+fn render_login_page(req: &AuthorizeRequest, client: &Client, error: Option<&str>) -> String {
+    let hidden_fields = [
+        ("response_type", &req.response_type),
+        ("client_id", &req.client_id),
+        ("redirect_uri", &req.redirect_uri),
+        ("scope", &req.scope),
+        ("state", &req.state),
+        ("code_challenge", &req.code_challenge),
+        ("code_challenge_method", &req.code_challenge_method),
+        ("nonce", &req.nonce),
+    ]
+    .iter()
+    .filter_map(|(name, value)| {
+        value.as_deref().map(|v| {
+            format!(
+                r#"<input type="hidden" name="{}" value="{}">"#,
+                name,
+                html_escape(v)
+            )
+        })
+    })
+    .collect::<Vec<_>>()
+    .join("\n      ");
+
+    let error = error
+        .map(|e| format!(r#"<p style="color:red">{}</p>"#, html_escape(e)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><meta charset="utf-8"><title>Sign in</title></head>
+  <body>
+    <h1>Sign in to {client_name}</h1>
+    {error}
+    <p>Requested scope: {scope}</p>
+    <form method="post" action="/authorize">
+      {hidden_fields}
+      <label>Email <input type="email" name="email" required></label><br>
+      <label>Password <input type="password" name="password" required></label><br>
+      <label>Authenticator code (if enabled) <input type="text" name="mfa_code" inputmode="numeric" autocomplete="one-time-code"></label><br>
+      <button type="submit" name="decision" value="approve">Sign in and allow</button>
+      <button type="submit" name="decision" value="deny" formnovalidate>Deny</button>
+    </form>
+  </body>
+</html>"#,
+        client_name = html_escape(&client.client_name),
+        error = error,
+        scope = html_escape(req.scope.as_deref().unwrap_or("")),
+        hidden_fields = hidden_fields,
+    )
+}
+
+fn render_error_page(message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><meta charset="utf-8"><title>Authorization error</title></head>
+  <body><h1>Authorization error</h1><p>{}</p></body>
+</html>"#,
+        html_escape(message)
+    )
+}
@@ -0,0 +1,183 @@
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, web};
+use mongodb::Database;
+use mongodb::bson::doc;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config::{base_url, issuer};
+use crate::models::client::SUPPORTED_GRANT_TYPES;
+use crate::models::user::{User, subject_filter};
+use crate::utils::jwt::Claims;
+use crate::utils::keys::{algorithms, public_jwks};
+use crate::utils::oauth::OAuthError;
+use crate::utils::scope::{SUPPORTED_SCOPES, has_scope};
+
+/// OpenID Provider metadata (OIDC Discovery section 3)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    pub registration_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+//-----------------------------------------
+/// OpenID Connect discovery document
+#[utoipa::path(
+    get,
+    path = "/.well-known/openid-configuration",
+    operation_id = "openid_configuration",
+    tag = "OpenID Connect",
+    security(), // 빈 security - 인증 불필요
+    responses(
+        (status = 200, description = "OpenID Provider metadata", body = OpenIdConfiguration)
+    )
+)]
+#[get("/.well-known/openid-configuration")]
+pub async fn openid_configuration() -> HttpResponse {
+    let base_url = base_url();
+
+    HttpResponse::Ok().json(OpenIdConfiguration {
+        issuer: issuer(),
+        authorization_endpoint: format!("{}/authorize", base_url),
+        token_endpoint: format!("{}/token", base_url),
+        userinfo_endpoint: format!("{}/userinfo", base_url),
+        device_authorization_endpoint: format!("{}/device_authorization", base_url),
+        introspection_endpoint: format!("{}/introspect", base_url),
+        revocation_endpoint: format!("{}/revoke", base_url),
+        registration_endpoint: format!("{}/register-client", base_url),
+        jwks_uri: format!("{}/.well-known/jwks.json", base_url),
+        scopes_supported: strings(&SUPPORTED_SCOPES),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&SUPPORTED_GRANT_TYPES),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: algorithms(),
+        token_endpoint_auth_methods_supported: strings(&[
+            "client_secret_basic",
+            "client_secret_post",
+            "none",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "iss",
+            "sub",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "at_hash",
+            "preferred_username",
+            "email",
+            "email_verified",
+        ]),
+    })
+}
+
+//-----------------------------------------
+/// Public signing keys (JWK Set)
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    operation_id = "jwks",
+    tag = "OpenID Connect",
+    security(), // 빈 security - 인증 불필요
+    responses(
+        (status = 200, description = "JSON Web Key Set used to verify issued tokens", body = Jwks)
+    )
+)]
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(public_jwks())
+}
+
+/// OIDC Core section 5.3.2 UserInfo response, claims depend on the granted scopes
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "sub": "665f1c2e8b3e4a2d9c0b1a2f",
+    "preferred_username": "djamware",
+    "email": "user@example.com",
+    "email_verified": false
+}))]
+pub struct UserInfo {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>, // profile scope
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>, // email scope
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>, // email scope
+}
+
+//-----------------------------------------
+/// OpenID Connect UserInfo endpoint
+#[utoipa::path(
+    get,
+    path = "/userinfo",
+    operation_id = "userinfo",
+    tag = "OpenID Connect",
+    security(
+        ("bearer_auth" = ["openid"])
+    ),
+    responses(
+        (status = 200, description = "Claims about the user, filtered by the token's scopes", body = UserInfo),
+        (status = 401, description = "Missing, invalid or revoked access token", body = OAuthError),
+        (status = 403, description = "insufficient_scope - the token wasn't granted the openid scope", body = OAuthError)
+    )
+)]
+// Protected route, also served for POST (OIDC Core section 5.3.1)
+pub async fn userinfo(
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse, OAuthError> {
+    let claims = req
+        .extensions()
+        .get::<Claims>()
+        .cloned()
+        .filter(|claims| claims.token_type == "access")
+        .ok_or_else(|| OAuthError::invalid_token("An access token is required"))?;
+
+    if claims.is_client() {
+        return Err(OAuthError::invalid_token(
+            "Client tokens are not bound to a user",
+        ));
+    }
+
+    // the openid scope is enforced by RequireScope
+    let scope = claims.scope.as_deref();
+
+    let user = db
+        .collection::<User>("users")
+        .find_one(subject_filter(&claims.sub))
+        .await
+        .map_err(|e| OAuthError::server_error(format!("Database error: {}", e)))?
+        .ok_or_else(|| OAuthError::invalid_token("User no longer exists"))?;
+
+    let email_scope = has_scope(scope, "email");
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(UserInfo {
+            sub: claims.sub,
+            preferred_username: has_scope(scope, "profile").then(|| user.username.clone()),
+            email: email_scope.then(|| user.email.clone()),
+            email_verified: email_scope.then_some(user.email_verified),
+        }))
+}
@@ -0,0 +1,298 @@
+use actix_web::{HttpResponse, post, web};
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::config::base_url;
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::password_reset::PasswordReset;
+use crate::models::user::User;
+use crate::utils::audit::record_security_event;
+use crate::utils::hash::{hash_password, verify_password};
+use crate::utils::lockout::{
+    locked_for, locked_response, record_login_failure, record_login_success, unlock_account,
+};
+use crate::utils::mailer::{Email, Mailer};
+use crate::utils::refresh_token::revoke_user_refresh_tokens;
+use crate::utils::token::{generate_token, hash_token};
+use crate::utils::token_version::bump_token_version;
+
+pub const PASSWORD_RESET_MINUTES: i64 = 30;
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "email": "admin@djamware.com" }))]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+//-----------------------------------------
+/// Email a password reset token
+#[utoipa::path(
+    post,
+    path = "/password/forgot",
+    operation_id = "forgot_password",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 200, description = "Always returned, whether or not the email belongs to an account", body = String)
+    )
+)]
+#[post("/password/forgot")]
+pub async fn forgot_password(
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<ForgotPasswordRequest>,
+) -> HttpResponse {
+    // the response never reveals whether the account exists, not even by its timing:
+    // the token is stored and mailed after the response
+    match db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &payload.email })
+        .await
+    {
+        Ok(Some(user)) => {
+            let db = db.clone();
+            let mailer = mailer.clone();
+            actix_rt::spawn(async move {
+                if let Err(e) = send_reset_email(&db, mailer.get_ref(), &user).await {
+                    eprintln!("Failed to send password reset email: {}", e);
+                }
+            });
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Database error: {}", e),
+    }
+
+    HttpResponse::Ok().body("If the address belongs to an account, a password reset token was sent")
+}
+
+/// Replace any pending reset of the user with a new token and email it
+async fn send_reset_email(db: &Database, mailer: &dyn Mailer, user: &User) -> Result<(), String> {
+    let user_id = user.id.ok_or("User has no id")?;
+    let token = generate_token();
+
+    let collection = db.collection::<PasswordReset>("password_resets");
+    collection
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    collection
+        .insert_one(PasswordReset {
+            id: None,
+            token_hash: hash_token(&token),
+            user_id,
+            expires_at: DateTime::from_millis(
+                (Utc::now() + Duration::minutes(PASSWORD_RESET_MINUTES)).timestamp_millis(),
+            ),
+        })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    mailer
+        .send(&Email {
+            to: user.email.clone(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hello {},\n\nSomeone asked to reset the password of your account. If it was you, send this token with a new password to {}/password/reset:\n\n{}\n\nThe token expires in {} minutes. If you didn't ask for it, you can ignore this email.\n",
+                user.username,
+                base_url(),
+                token,
+                PASSWORD_RESET_MINUTES
+            ),
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "token": "reset_token_from_the_email", "new_password": "my-new-password" }))]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+//-----------------------------------------
+/// Set a new password with an emailed reset token
+#[utoipa::path(
+    post,
+    path = "/password/reset",
+    operation_id = "reset_password",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Password changed, every session of the user was signed out and any login lock lifted", body = String, example = json!("Password reset successfully")),
+        (status = 400, description = "Invalid, expired or already used token", body = String),
+        (status = 500, description = "Failed to hash password or update user", body = String)
+    )
+)]
+#[post("/password/reset")]
+pub async fn reset_password(
+    db: web::Data<Database>,
+    payload: web::Json<ResetPasswordRequest>,
+) -> HttpResponse {
+    // tokens are single-use: delete on first lookup
+    let reset = match db
+        .collection::<PasswordReset>("password_resets")
+        .find_one_and_delete(doc! {
+            "token_hash": hash_token(&payload.token),
+            "expires_at": { "$gt": DateTime::now() },
+        })
+        .await
+    {
+        Ok(Some(reset)) => reset,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let password_hash = match hash_password(&payload.new_password) {
+        Ok(hash) => hash,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to hash password"),
+    };
+
+    // the emailed token also proves the address
+    let user = match db
+        .collection::<User>("users")
+        .find_one_and_update(
+            doc! { "_id": reset.user_id },
+            doc! { "$set": { "password": password_hash, "email_verified": true } },
+        )
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    // whoever knew the old password is signed out
+    if let Err(e) = sign_out_everywhere(&db, &user).await {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+    // failed logins were against the old password, so they no longer lock the owner out
+    if let Err(e) = unlock_account(&db, &user.email).await {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+    record_security_event(
+        &db,
+        "password_reset",
+        Some(&user.email),
+        None,
+        "Password reset with an emailed token, all sessions revoked",
+    )
+    .await;
+
+    HttpResponse::Ok().body("Password reset successfully")
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "current_password": "my-old-password", "new_password": "my-new-password" }))]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+//-----------------------------------------
+/// Change the password of the signed-in user
+#[utoipa::path(
+    post,
+    path = "/api/password",
+    operation_id = "change_password",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed, every access and refresh token of the user was revoked", body = String, example = json!("Password changed successfully, please log in again")),
+        (status = 400, description = "Current password is incorrect", body = String),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String),
+        (status = 429, description = "Too many failed logins or wrong current passwords for the email; see Retry-After", body = String),
+        (status = 500, description = "Failed to hash password or update user", body = String)
+    )
+)]
+// Protected route
+pub async fn change_password(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+    payload: web::Json<ChangePasswordRequest>,
+) -> HttpResponse {
+    // third-party apps acting for the user don't get to change the password
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change the password");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    // a stolen access token mustn't become a way around the login lockout
+    match locked_for(&db, &account.email).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => return locked_response(seconds),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    }
+
+    match verify_password(&account.password, &payload.current_password) {
+        Ok(true) => {}
+        _ => {
+            if let Err(e) = record_login_failure(&db, &account.email).await {
+                eprintln!("Failed to record login failure: {}", e);
+            }
+            return HttpResponse::BadRequest().body("Current password is incorrect");
+        }
+    }
+    if let Err(e) = record_login_success(&db, &account.email).await {
+        eprintln!("Failed to reset failed logins: {}", e);
+    }
+
+    let password_hash = match hash_password(&payload.new_password) {
+        Ok(hash) => hash,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to hash password"),
+    };
+
+    if let Err(e) = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id },
+            doc! { "$set": { "password": password_hash } },
+        )
+        .await
+    {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+
+    // the token of this request is signed out too
+    if let Err(e) = sign_out_everywhere(&db, &account).await {
+        return HttpResponse::InternalServerError().body(format!("Database update failed: {}", e));
+    }
+    record_security_event(
+        &db,
+        "password_changed",
+        Some(&account.email),
+        None,
+        "Password changed, all tokens revoked",
+    )
+    .await;
+
+    HttpResponse::Ok().body("Password changed successfully, please log in again")
+}
+
+/// Revoke the user's refresh tokens and bump the token version, so access tokens issued so far stop working too
+async fn sign_out_everywhere(db: &Database, user: &User) -> Result<(), mongodb::error::Error> {
+    if let Some(user_id) = user.id {
+        revoke_user_refresh_tokens(db, user_id).await?;
+        bump_token_version(db, user_id).await?;
+    }
+    Ok(())
+}
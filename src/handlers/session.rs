@@ -0,0 +1,181 @@
+use actix_web::error::{ErrorInternalServerError, ErrorNotFound};
+use actix_web::{Error, HttpResponse, web};
+use futures_util::TryStreamExt;
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use mongodb::options::FindOptions;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::session::Session;
+use crate::utils::refresh_token::{revoke_refresh_family, revoke_user_refresh_tokens};
+use crate::utils::revocation::revoke_claims;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "session_id": "0f8fad5b-d9cb-469f-a165-70867728950e",
+    "device_name": "Work laptop",
+    "user_agent": "Mozilla/5.0",
+    "ip_address": "203.0.113.7",
+    "client_id": null,
+    "created_at": "2025-06-01T09:00:00Z",
+    "last_used_at": "2025-06-01T10:30:00Z",
+    "current": true
+}))]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub client_id: Option<String>, // OAuth2 client that holds the session, if any
+    pub created_at: String,        // RFC 3339
+    pub last_used_at: String,
+    pub current: bool, // the session of the token making this request
+}
+
+impl SessionInfo {
+    fn from_session(session: Session, current_session_id: Option<&str>) -> Self {
+        SessionInfo {
+            current: current_session_id == Some(session.session_id.as_str()),
+            session_id: session.session_id,
+            device_name: session.device_name,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            client_id: session.client_id,
+            created_at: session
+                .created_at
+                .try_to_rfc3339_string()
+                .unwrap_or_default(),
+            last_used_at: session
+                .last_used_at
+                .try_to_rfc3339_string()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+//-----------------------------------------
+/// List the user's active sessions
+#[utoipa::path(
+    get,
+    path = "/api/sessions",
+    operation_id = "list_sessions",
+    tag = "Sessions",
+    security(
+        ("bearer_auth" = ["sessions"])
+    ),
+    responses(
+        (status = 200, description = "Active sessions, most recently used first", body = [SessionInfo]),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - insufficient_scope")
+    )
+)]
+// Protected route
+pub async fn list_sessions(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, Error> {
+    let account = user.load_user(&db).await?;
+
+    let sessions: Vec<Session> = db
+        .collection::<Session>("sessions")
+        .find(doc! {
+            "user_id": account.id,
+            "expires_at": { "$gt": DateTime::now() },
+        })
+        .with_options(
+            FindOptions::builder()
+                .sort(doc! { "last_used_at": -1 })
+                .build(),
+        )
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .try_collect()
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let sessions: Vec<SessionInfo> = sessions
+        .into_iter()
+        .map(|session| SessionInfo::from_session(session, user.claims.sid.as_deref()))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+//-----------------------------------------
+/// Revoke one of the user's sessions
+#[utoipa::path(
+    delete,
+    path = "/api/sessions/{session_id}",
+    operation_id = "revoke_session",
+    tag = "Sessions",
+    security(
+        ("bearer_auth" = ["sessions"])
+    ),
+    params(
+        ("session_id" = String, Path, description = "Session to revoke")
+    ),
+    responses(
+        (status = 204, description = "Session revoked, its access and refresh tokens no longer work"),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - insufficient_scope"),
+        (status = 404, description = "No such session for this user")
+    )
+)]
+// Protected route
+pub async fn revoke_session(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let session_id = path.into_inner();
+    let account = user.load_user(&db).await?;
+
+    let session = db
+        .collection::<Session>("sessions")
+        .find_one(doc! { "session_id": &session_id, "user_id": account.id })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorNotFound("Session not found"))?;
+
+    revoke_refresh_family(&db, &session.session_id)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+//-----------------------------------------
+/// Revoke all of the user's sessions, including the current one
+#[utoipa::path(
+    delete,
+    path = "/api/sessions",
+    operation_id = "revoke_all_sessions",
+    tag = "Sessions",
+    security(
+        ("bearer_auth" = ["sessions"])
+    ),
+    responses(
+        (status = 204, description = "All sessions revoked"),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - insufficient_scope")
+    )
+)]
+// Protected route
+pub async fn revoke_all_sessions(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, Error> {
+    if let Some(user_id) = user.load_user(&db).await?.id {
+        revoke_user_refresh_tokens(&db, user_id)
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database update failed: {}", e)))?;
+    }
+    // also covers a token issued before sessions existed
+    revoke_claims(&db, &user.claims)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Failed to revoke token: {}", e)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
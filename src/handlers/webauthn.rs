@@ -0,0 +1,651 @@
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::{Error, HttpRequest, HttpResponse, post, web};
+use base64::Engine;
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, doc, to_bson};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::{email_verification_required, webauthn_rp_name};
+use crate::handlers::auth::issue_token_pair;
+use crate::middleware::authenticated_user::AuthenticatedUser;
+use crate::models::mfa_challenge::MfaChallenge;
+use crate::models::user::User;
+use crate::models::webauthn_challenge::WebAuthnChallenge;
+use crate::models::webauthn_credential::WebAuthnCredential;
+use crate::utils::audit::record_security_event;
+use crate::utils::lockout::{
+    locked_for, locked_response, record_login_failure, record_login_success,
+};
+use crate::utils::mfa::new_recovery_codes;
+use crate::utils::scope::{resolve_client_scope, restrict_to_user};
+use crate::utils::session::start_session;
+use crate::utils::token::{generate_token, hash_token};
+use crate::utils::webauthn::{
+    BASE64URL, RelyingParty, SUPPORTED_ALGORITHMS, client_challenge, normalize_credential_id,
+    sign_count_advances, verify_assertion, verify_registration,
+};
+
+pub const WEBAUTHN_CHALLENGE_MINUTES: i64 = 5;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String, // base64url user handle: the ObjectId bytes
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    pub kind: String, // "public-key"
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: String, // "public-key"
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+/// `PublicKeyCredentialCreationOptions` in WebAuthn's JSON form, for `navigator.credentials.create({ publicKey })`
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameter>,
+    pub timeout: i64, // milliseconds
+    pub attestation: String,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+}
+
+/// `PublicKeyCredentialRequestOptions` in WebAuthn's JSON form, for `navigator.credentials.get({ publicKey })`
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: i64, // milliseconds
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+/// WebAuthn user handle of a user
+fn user_handle(user_id: &ObjectId) -> String {
+    BASE64URL.encode(user_id.bytes())
+}
+
+fn credential_descriptors(user: &User) -> Vec<CredentialDescriptor> {
+    user.webauthn_credentials
+        .iter()
+        .map(|credential| CredentialDescriptor {
+            kind: "public-key".to_string(),
+            id: credential.credential_id.clone(),
+        })
+        .collect()
+}
+
+/// Store a challenge for a ceremony, returns the challenge to send to the browser
+async fn create_challenge(
+    db: &Database,
+    ceremony: &str,
+    user_id: Option<ObjectId>,
+) -> Result<String, mongodb::error::Error> {
+    let challenge = generate_token();
+    db.collection::<WebAuthnChallenge>("webauthn_challenges")
+        .insert_one(WebAuthnChallenge {
+            id: None,
+            challenge_hash: hash_token(&challenge),
+            ceremony: ceremony.to_string(),
+            user_id,
+            expires_at: DateTime::from_millis(
+                (Utc::now() + Duration::minutes(WEBAUTHN_CHALLENGE_MINUTES)).timestamp_millis(),
+            ),
+        })
+        .await?;
+    Ok(challenge)
+}
+
+/// Take the pending ceremony a `clientDataJSON` answers; challenges are single-use
+async fn take_challenge(
+    db: &Database,
+    client_data_json: &str,
+    ceremony: &str,
+) -> Result<Option<(String, WebAuthnChallenge)>, mongodb::error::Error> {
+    let Ok(challenge) = client_challenge(client_data_json) else {
+        return Ok(None);
+    };
+    let pending = db
+        .collection::<WebAuthnChallenge>("webauthn_challenges")
+        .find_one_and_delete(doc! {
+            "challenge_hash": hash_token(&challenge),
+            "ceremony": ceremony,
+            "expires_at": { "$gt": DateTime::now() },
+        })
+        .await?;
+    Ok(pending.map(|pending| (challenge, pending)))
+}
+
+//-----------------------------------------
+/// Start registering a passkey or security key for the signed-in user
+#[utoipa::path(
+    post,
+    path = "/api/webauthn/register/options",
+    operation_id = "webauthn_register_options",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Options for navigator.credentials.create, valid for 5 minutes", body = CreationOptions),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String)
+    )
+)]
+// Protected route
+pub async fn webauthn_register_options(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+) -> HttpResponse {
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change two-factor authentication");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let Some(user_id) = account.id else {
+        return HttpResponse::InternalServerError().body("User has no id");
+    };
+
+    let challenge = match create_challenge(&db, "registration", Some(user_id)).await {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let rp = RelyingParty::from_env();
+    HttpResponse::Ok().json(CreationOptions {
+        challenge,
+        rp: RelyingPartyEntity {
+            id: rp.id,
+            name: webauthn_rp_name(),
+        },
+        user: UserEntity {
+            id: user_handle(&user_id),
+            name: account.email.clone(),
+            display_name: account.username.clone(),
+        },
+        pub_key_cred_params: SUPPORTED_ALGORITHMS
+            .iter()
+            .map(|alg| CredentialParameter {
+                kind: "public-key".to_string(),
+                alg: *alg,
+            })
+            .collect(),
+        timeout: WEBAUTHN_CHALLENGE_MINUTES * 60 * 1000,
+        attestation: "none".to_string(),
+        exclude_credentials: credential_descriptors(&account),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred".to_string(),
+            user_verification: "preferred".to_string(),
+        },
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// `PublicKeyCredential` from `navigator.credentials.create`, as `toJSON()` encodes it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "name": "YubiKey",
+    "credential": {
+        "id": "credential_id_base64url",
+        "response": {
+            "clientDataJSON": "base64url",
+            "attestationObject": "base64url"
+        }
+    }
+}))]
+pub struct WebAuthnRegistrationRequest {
+    #[serde(default)]
+    pub name: Option<String>, // label shown to the user
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({ "credential_id": "credential_id_base64url", "recovery_codes": ["k7m2p-x9qrt", "a3bcd-ef4gh"] }))]
+pub struct WebAuthnRegistrationResponse {
+    pub credential_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>, // only when the user had none yet
+}
+
+//-----------------------------------------
+/// Finish registering a passkey or security key
+#[utoipa::path(
+    post,
+    path = "/api/webauthn/register",
+    operation_id = "webauthn_register",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = WebAuthnRegistrationRequest,
+    responses(
+        (status = 200, description = "Credential registered; recovery codes are included when the user had none", body = WebAuthnRegistrationResponse),
+        (status = 400, description = "Unknown or expired challenge, or the attestation doesn't verify", body = String),
+        (status = 401, description = "Unauthorized - Missing or invalid token, or a client_credentials token without a user"),
+        (status = 403, description = "Forbidden - token issued to an OAuth2 client", body = String),
+        (status = 409, description = "Credential already registered", body = String)
+    )
+)]
+// Protected route
+pub async fn webauthn_register(
+    db: web::Data<Database>,
+    user: AuthenticatedUser,
+    payload: web::Json<WebAuthnRegistrationRequest>,
+) -> HttpResponse {
+    if user.claims.client_id.is_some() {
+        return HttpResponse::Forbidden()
+            .body("Tokens issued to an OAuth2 client can't change two-factor authentication");
+    }
+
+    let account = match user.load_user(&db).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    let response = &payload.credential.response;
+    let challenge = match take_challenge(&db, &response.client_data_json, "registration").await {
+        Ok(Some((challenge, pending))) if pending.user_id == account.id => challenge,
+        Ok(_) => return HttpResponse::BadRequest().body("Unknown or expired challenge"),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let new_credential = match verify_registration(
+        &response.client_data_json,
+        &response.attestation_object,
+        &challenge,
+        &RelyingParty::from_env(),
+    ) {
+        Ok(credential) => credential,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    if new_credential.credential_id != normalize_credential_id(&payload.credential.id) {
+        return HttpResponse::BadRequest()
+            .body("Credential id doesn't match the attested credential");
+    }
+
+    let credential = WebAuthnCredential {
+        credential_id: new_credential.credential_id.clone(),
+        public_key: new_credential.public_key,
+        algorithm: new_credential.algorithm,
+        sign_count: new_credential.sign_count as i64,
+        name: payload.name.clone(),
+        created_at: DateTime::now(),
+        last_used_at: None,
+    };
+    let credential = match to_bson(&credential) {
+        Ok(credential) => credential,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    // a first second factor comes with recovery codes
+    let mut update = doc! { "$push": { "webauthn_credentials": credential } };
+    let mut recovery_codes = None;
+    if account.recovery_codes.is_empty() {
+        let (codes, hashes) = match new_recovery_codes() {
+            Ok(codes) => codes,
+            Err(_) => {
+                return HttpResponse::InternalServerError().body("Failed to hash recovery codes");
+            }
+        };
+        update.insert("$set", doc! { "recovery_codes": hashes });
+        recovery_codes = Some(codes);
+    }
+
+    match db
+        .collection::<User>("users")
+        .update_one(
+            doc! {
+                "_id": account.id,
+                "webauthn_credentials.credential_id": { "$ne": &new_credential.credential_id },
+            },
+            update,
+        )
+        .await
+    {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => return HttpResponse::Conflict().body("Credential already registered"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Database update failed: {}", e));
+        }
+    }
+    record_security_event(
+        &db,
+        "webauthn_registered",
+        Some(&account.email),
+        None,
+        format!(
+            "WebAuthn credential {} registered",
+            payload
+                .name
+                .as_deref()
+                .unwrap_or(&new_credential.credential_id)
+        ),
+    )
+    .await;
+
+    HttpResponse::Ok().json(WebAuthnRegistrationResponse {
+        credential_id: new_credential.credential_id,
+        recovery_codes,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({ "mfa_token": "challenge_token_from_login" }))]
+pub struct WebAuthnLoginOptionsRequest {
+    #[serde(default)]
+    pub mfa_token: Option<String>, // second factor of a password login; omit for a passwordless login
+}
+
+//-----------------------------------------
+/// Start a WebAuthn login, passwordless or as the second factor of /login
+#[utoipa::path(
+    post,
+    path = "/webauthn/login/options",
+    operation_id = "webauthn_login_options",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = WebAuthnLoginOptionsRequest,
+    responses(
+        (status = 200, description = "Options for navigator.credentials.get, valid for 5 minutes", body = RequestOptions),
+        (status = 401, description = "Invalid or expired MFA challenge"),
+        (status = 500, description = "Database error")
+    )
+)]
+#[post("/webauthn/login/options")]
+pub async fn webauthn_login_options(
+    db: web::Data<Database>,
+    payload: web::Json<WebAuthnLoginOptionsRequest>,
+) -> Result<HttpResponse, Error> {
+    // second factor: only the user's own credentials; passwordless: any discoverable credential
+    let user = match payload.mfa_token.as_deref() {
+        Some(mfa_token) => Some(find_mfa_user(&db, mfa_token).await?),
+        None => None,
+    };
+
+    let challenge = create_challenge(&db, "authentication", user.as_ref().and_then(|u| u.id))
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(RequestOptions {
+        challenge,
+        rp_id: RelyingParty::from_env().id,
+        timeout: WEBAUTHN_CHALLENGE_MINUTES * 60 * 1000,
+        allow_credentials: user
+            .as_ref()
+            .map(credential_descriptors)
+            .unwrap_or_default(),
+        // a passkey alone must prove the user (PIN or biometrics)
+        user_verification: if user.is_some() {
+            "discouraged"
+        } else {
+            "required"
+        }
+        .to_string(),
+    }))
+}
+
+/// The user of a pending MFA challenge
+async fn find_mfa_user(db: &Database, mfa_token: &str) -> Result<User, Error> {
+    let challenge = db
+        .collection::<MfaChallenge>("mfa_challenges")
+        .find_one(doc! {
+            "token_hash": hash_token(mfa_token),
+            "expires_at": { "$gt": DateTime::now() },
+        })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorUnauthorized("Invalid or expired MFA challenge"))?;
+
+    db.collection::<User>("users")
+        .find_one(doc! { "_id": challenge.user_id })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .ok_or_else(|| ErrorUnauthorized("Invalid or expired MFA challenge"))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle", default)]
+    pub user_handle: Option<String>,
+}
+
+/// `PublicKeyCredential` from `navigator.credentials.get`, as `toJSON()` encodes it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssertionCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "credential": {
+        "id": "credential_id_base64url",
+        "response": {
+            "clientDataJSON": "base64url",
+            "authenticatorData": "base64url",
+            "signature": "base64url",
+            "userHandle": "base64url"
+        }
+    },
+    "device_name": "Work laptop"
+}))]
+pub struct WebAuthnLoginRequest {
+    pub credential: AssertionCredential,
+    #[serde(default)]
+    pub mfa_token: Option<String>, // set when the credential is the second factor of /login
+    #[serde(default)]
+    pub device_name: Option<String>, // passwordless only, a second factor keeps the one given to /login
+    #[serde(default)]
+    pub scope: Option<String>, // passwordless only
+}
+
+//-----------------------------------------
+/// Finish a WebAuthn login and get tokens
+#[utoipa::path(
+    post,
+    path = "/webauthn/login",
+    operation_id = "webauthn_login",
+    tag = "Authentication",
+    security(), // 빈 security - 인증 불필요
+    request_body = WebAuthnLoginRequest,
+    responses(
+        (status = 200, description = "Login successful, returns access and refresh tokens", body = TokenResponse),
+        (status = 400, description = "invalid_scope - unknown scope or none the user may grant", body = OAuthError),
+        (status = 401, description = "Unknown or expired challenge, unknown credential, or the assertion doesn't verify"),
+        (status = 403, description = "Email address not verified (when REQUIRE_EMAIL_VERIFICATION is set)"),
+        (status = 429, description = "Account locked after too many failed logins, see Retry-After", body = String),
+        (status = 500, description = "Database or token generation error")
+    )
+)]
+#[post("/webauthn/login")]
+pub async fn webauthn_login(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    payload: web::Json<WebAuthnLoginRequest>,
+) -> Result<HttpResponse, Error> {
+    let credential = &payload.credential;
+    let (challenge, pending) =
+        take_challenge(&db, &credential.response.client_data_json, "authentication")
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+            .ok_or_else(|| ErrorUnauthorized("Unknown or expired challenge"))?;
+
+    // a second factor must answer a challenge issued for that login
+    let second_factor = payload.mfa_token.is_some();
+    if second_factor != pending.user_id.is_some() {
+        return Err(ErrorUnauthorized("Unknown or expired challenge"));
+    }
+
+    let credential_id = normalize_credential_id(&credential.id);
+    let users = db.collection::<User>("users");
+    let user = users
+        .find_one(doc! { "webauthn_credentials.credential_id": credential_id })
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+        .filter(|user| pending.user_id.is_none() || pending.user_id == user.id)
+        .ok_or_else(|| ErrorUnauthorized("Unknown credential"))?;
+    let user_id = user
+        .id
+        .ok_or_else(|| ErrorInternalServerError("User has no id"))?;
+    if let Some(seconds) = locked_for(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+    {
+        return Ok(locked_response(seconds));
+    }
+    if credential
+        .response
+        .user_handle
+        .as_deref()
+        .is_some_and(|handle| !handle.is_empty() && handle != user_handle(&user_id))
+    {
+        return Err(ErrorUnauthorized("Unknown credential"));
+    }
+    let stored = user
+        .webauthn_credentials
+        .iter()
+        .find(|stored| stored.credential_id == credential_id)
+        .ok_or_else(|| ErrorUnauthorized("Unknown credential"))?;
+
+    let sign_count = match verify_assertion(
+        &credential.response.client_data_json,
+        &credential.response.authenticator_data,
+        &credential.response.signature,
+        &challenge,
+        &RelyingParty::from_env(),
+        &stored.public_key,
+        !second_factor,
+    ) {
+        Ok(sign_count) => sign_count as i64,
+        Err(e) => {
+            // a failed assertion counts towards the lockout like a wrong password or code
+            record_login_failure(&db, &user.email)
+                .await
+                .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+            return Err(ErrorUnauthorized(e));
+        }
+    };
+
+    if !sign_count_advances(stored.sign_count, sign_count) {
+        record_login_failure(&db, &user.email)
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+        record_security_event(
+            &db,
+            "webauthn_counter_error",
+            Some(&user.email),
+            None,
+            format!(
+                "Signature counter of credential {} went from {} to {}, possibly a cloned authenticator",
+                stored.credential_id, stored.sign_count, sign_count
+            ),
+        )
+        .await;
+        return Err(ErrorUnauthorized("Invalid signature counter"));
+    }
+    users
+        .update_one(
+            doc! { "_id": user_id, "webauthn_credentials.credential_id": credential_id },
+            doc! { "$set": {
+                "webauthn_credentials.$.sign_count": sign_count,
+                "webauthn_credentials.$.last_used_at": DateTime::now(),
+            } },
+        )
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let (scope, device_name) = match payload.mfa_token.as_deref() {
+        // the password step settled scope and device; its challenge is used up now
+        Some(mfa_token) => {
+            let challenge = db
+                .collection::<MfaChallenge>("mfa_challenges")
+                .find_one_and_delete(doc! {
+                    "token_hash": hash_token(mfa_token),
+                    "user_id": user_id,
+                    "expires_at": { "$gt": DateTime::now() },
+                })
+                .await
+                .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+                .ok_or_else(|| ErrorUnauthorized("Invalid or expired MFA challenge"))?;
+            (challenge.scope, challenge.device_name)
+        }
+        None => {
+            if email_verification_required() && !user.email_verified {
+                return Err(ErrorForbidden("Email address not verified"));
+            }
+            let scope = resolve_client_scope(payload.scope.as_deref(), None)?;
+            (
+                restrict_to_user(&scope, &user.scopes)?,
+                payload.device_name.clone(),
+            )
+        }
+    };
+
+    let session_id = start_session(&db, &req, user_id, None, device_name.as_deref())
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Failed to start session: {}", e)))?;
+
+    let tokens = issue_token_pair(&db, &user, None, Some(&scope), &session_id).await?;
+    record_login_success(&db, &user.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
@@ -12,9 +12,26 @@ mod middleware;
 mod models;
 mod utils;
 
-use config::connect_db;
+use config::{bootstrap_admin, connect_db, create_indexes};
+use handlers::admin::{set_user_roles, unlock_user};
 use handlers::auth::{get_profile, login, logout, refresh_token, register_user};
+use handlers::client::{delete_client, read_client, register_client, update_client};
+use handlers::device::{device_authorization, device_verification, device_verification_submit};
+use handlers::email_verification::{resend_verification_email, verify_email};
+use handlers::mfa::{confirm_totp, enroll_totp, login_mfa, regenerate_recovery_codes};
+use handlers::oauth::{authorize, authorize_login, introspect, revoke, token};
+use handlers::oidc::{jwks, openid_configuration, userinfo};
+use handlers::password::{change_password, forgot_password, reset_password};
+use handlers::session::{list_sessions, revoke_all_sessions, revoke_session};
+use handlers::webauthn::{
+    webauthn_login, webauthn_login_options, webauthn_register, webauthn_register_options,
+};
 use middleware::jwt_auth::AuthMiddleware;
+use middleware::require_role::RequireRole;
+use middleware::require_scope::RequireScope;
+use utils::jwt::ValidationPolicy;
+use utils::mailer::mailer_from_env;
+use utils::role::ROLE_ADMIN;
 
 struct SecurityAddon;
 
@@ -38,18 +55,95 @@ impl Modify for SecurityAddon {
 #[openapi(
     paths(
         handlers::auth::register_user,
+        handlers::email_verification::verify_email,
+        handlers::email_verification::resend_verification_email,
+        handlers::password::forgot_password,
+        handlers::password::reset_password,
         handlers::auth::login,
+        handlers::mfa::login_mfa,
+        handlers::webauthn::webauthn_login_options,
+        handlers::webauthn::webauthn_login,
         handlers::auth::refresh_token,
         handlers::auth::get_profile,
-        handlers::auth::logout
+        handlers::password::change_password,
+        handlers::mfa::enroll_totp,
+        handlers::mfa::confirm_totp,
+        handlers::mfa::regenerate_recovery_codes,
+        handlers::webauthn::webauthn_register_options,
+        handlers::webauthn::webauthn_register,
+        handlers::auth::logout,
+        handlers::oauth::authorize,
+        handlers::oauth::authorize_login,
+        handlers::oauth::token,
+        handlers::oauth::introspect,
+        handlers::oauth::revoke,
+        handlers::device::device_authorization,
+        handlers::device::device_verification,
+        handlers::device::device_verification_submit,
+        handlers::client::register_client,
+        handlers::client::read_client,
+        handlers::client::update_client,
+        handlers::client::delete_client,
+        handlers::oidc::openid_configuration,
+        handlers::oidc::jwks,
+        handlers::oidc::userinfo,
+        handlers::session::list_sessions,
+        handlers::session::revoke_session,
+        handlers::session::revoke_all_sessions,
+        handlers::admin::set_user_roles,
+        handlers::admin::unlock_user
     ),
     components(
         schemas(
             handlers::auth::RegisterRequest,
+            handlers::email_verification::ResendVerificationRequest,
+            handlers::password::ForgotPasswordRequest,
+            handlers::password::ResetPasswordRequest,
+            handlers::password::ChangePasswordRequest,
             handlers::auth::LoginRequest,
             handlers::auth::RefreshRequest,
             handlers::auth::TokenResponse,
+            handlers::auth::MfaChallengeResponse,
+            handlers::mfa::MfaLoginRequest,
+            handlers::mfa::TotpEnrollmentResponse,
+            handlers::mfa::TotpCodeRequest,
+            handlers::mfa::RecoveryCodesResponse,
+            handlers::webauthn::CreationOptions,
+            handlers::webauthn::RequestOptions,
+            handlers::webauthn::RelyingPartyEntity,
+            handlers::webauthn::UserEntity,
+            handlers::webauthn::CredentialParameter,
+            handlers::webauthn::CredentialDescriptor,
+            handlers::webauthn::AuthenticatorSelection,
+            handlers::webauthn::WebAuthnRegistrationRequest,
+            handlers::webauthn::RegistrationCredential,
+            handlers::webauthn::AttestationResponse,
+            handlers::webauthn::WebAuthnRegistrationResponse,
+            handlers::webauthn::WebAuthnLoginOptionsRequest,
+            handlers::webauthn::WebAuthnLoginRequest,
+            handlers::webauthn::AssertionCredential,
+            handlers::webauthn::AssertionResponse,
             handlers::auth::ProfileResponse,
+            handlers::oauth::AuthorizeRequest,
+            handlers::oauth::AuthorizeLoginForm,
+            handlers::oauth::TokenRequest,
+            handlers::oauth::OAuthTokenResponse,
+            handlers::oauth::IntrospectionRequest,
+            handlers::oauth::IntrospectionResponse,
+            handlers::oauth::RevocationRequest,
+            handlers::device::DeviceAuthorizationRequest,
+            handlers::device::DeviceAuthorizationResponse,
+            handlers::device::DeviceVerificationForm,
+            handlers::client::ClientMetadata,
+            handlers::client::ClientInformation,
+            handlers::oidc::OpenIdConfiguration,
+            handlers::oidc::UserInfo,
+            handlers::session::SessionInfo,
+            handlers::admin::UserRolesRequest,
+            handlers::admin::UserRolesResponse,
+            utils::keys::Jwks,
+            utils::keys::Jwk,
+            utils::oauth::OAuthError,
             utils::jwt::Claims
         )
     ),
@@ -59,7 +153,12 @@ impl Modify for SecurityAddon {
         ("bearer_auth" = []) // bearer_auth 옵션 제공
     ),
     tags(
-        (name = "Authentication", description = "User authentication and token management endpoints")
+        (name = "Authentication", description = "User authentication and token management endpoints"),
+        (name = "OAuth2", description = "OAuth2 authorization, device authorization, token, introspection and revocation endpoints"),
+        (name = "Client Registration", description = "RFC 7591/7592 dynamic client registration"),
+        (name = "Sessions", description = "List and revoke the user's login sessions"),
+        (name = "Admin", description = "Administrator-only user management"),
+        (name = "OpenID Connect", description = "OpenID Connect provider metadata, signing keys and UserInfo")
     ),
     info(
         title = "Rust OAuth2 API with Actix",
@@ -84,6 +183,24 @@ async fn index() -> impl Responder {
     "Rust/Actix OAuth2 API is running..."
 }
 
+/// Reload the JWT signing keys on SIGHUP so keys can be rotated without a restart
+#[cfg(unix)]
+fn spawn_key_reload_on_sighup() {
+    use actix_rt::signal::unix::{SignalKind, signal};
+
+    actix_rt::spawn(async {
+        let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+        while hangup.recv().await.is_some() {
+            if let Err(e) = utils::keys::reload_keys() {
+                eprintln!("Failed to reload JWT signing keys: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_key_reload_on_sighup() {}
+
 //-----------------------------
 // https://www.djamware.com/post/6836f7bc3069a919de614b05/rest-api-security-with-rust-mongodb-and-oauth2
 //-----------------------------
@@ -93,11 +210,21 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let db = connect_db().await;
+    create_indexes(&db).await;
+    bootstrap_admin(&db).await;
+    // fail fast on missing or invalid signing keys
+    utils::keys::key_set();
+    spawn_key_reload_on_sighup();
+
+    let policy = ValidationPolicy::from_env();
+    let mailer = web::Data::from(mailer_from_env());
 
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(policy.clone()))
+            .app_data(mailer.clone())
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}")
                     .url("/api-docs/openapi.json", ApiDoc::openapi())
@@ -109,12 +236,73 @@ async fn main() -> std::io::Result<()> {
             )
             .route("/", web::get().to(index))
             .route("/register", web::post().to(register_user))
+            .service(verify_email)
+            .service(resend_verification_email)
+            .service(forgot_password)
+            .service(reset_password)
             .service(login)
+            .service(login_mfa)
+            .service(webauthn_login_options)
+            .service(webauthn_login)
             .service(refresh_token)
+            .service(authorize)
+            .service(authorize_login)
+            .service(token)
+            .service(introspect)
+            .service(revoke)
+            .service(device_authorization)
+            .service(device_verification)
+            .service(device_verification_submit)
+            .service(register_client)
+            .service(read_client)
+            .service(update_client)
+            .service(delete_client)
+            .service(openid_configuration)
+            .service(jwks)
+            .service(
+                web::resource("/userinfo")
+                    .wrap(RequireScope::new(&["openid"]))
+                    .wrap(AuthMiddleware::with_policy(policy.clone()))
+                    .route(web::get().to(userinfo))
+                    .route(web::post().to(userinfo)),
+            )
             .service(
                 web::scope("/api")
-                    .wrap(AuthMiddleware)
-                    .route("/profile", web::get().to(get_profile)),
+                    .wrap(AuthMiddleware::with_policy(policy.clone()))
+                    .service(
+                        web::resource("/profile")
+                            .wrap(RequireScope::new(&["profile"]))
+                            .route(web::get().to(get_profile)),
+                    )
+                    .route("/password", web::post().to(change_password))
+                    .route("/mfa/totp", web::post().to(enroll_totp))
+                    .route("/mfa/totp/confirm", web::post().to(confirm_totp))
+                    .route(
+                        "/mfa/recovery-codes",
+                        web::post().to(regenerate_recovery_codes),
+                    )
+                    .route(
+                        "/webauthn/register/options",
+                        web::post().to(webauthn_register_options),
+                    )
+                    .route("/webauthn/register", web::post().to(webauthn_register))
+                    .service(
+                        web::resource("/sessions")
+                            .wrap(RequireScope::new(&["sessions"]))
+                            .route(web::get().to(list_sessions))
+                            .route(web::delete().to(revoke_all_sessions)),
+                    )
+                    .service(
+                        web::resource("/sessions/{session_id}")
+                            .wrap(RequireScope::new(&["sessions"]))
+                            .route(web::delete().to(revoke_session)),
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .wrap(RequireRole::new(&[ROLE_ADMIN]))
+                            .route("/users/{email}/roles", web::put().to(set_user_roles))
+                            .route("/users/{email}/unlock", web::post().to(unlock_user)),
+                    ),
             )
             .service(logout)
     })
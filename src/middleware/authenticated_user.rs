@@ -0,0 +1,92 @@
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, web};
+use futures_util::future::LocalBoxFuture;
+use mongodb::Database;
+
+use crate::models::user::{User, subject_filter};
+use crate::utils::jwt::{Claims, ValidationPolicy, validate_jwt};
+use crate::utils::revocation::is_revoked;
+use crate::utils::token_version::is_outdated;
+
+/// The user behind the request's access token. Take it as a handler argument:
+/// ```ignore
+/// pub async fn handler(user: AuthenticatedUser) -> HttpResponse
+/// ```
+/// Behind `AuthMiddleware` it reuses the claims the middleware validated; elsewhere it
+/// validates the Bearer token itself with the app's `ValidationPolicy` and checks revocation
+/// and the user's token version.
+/// Fails with `401 Unauthorized` whenever there is no user behind the request: no token, an
+/// invalid one, or a client_credentials token.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub claims: Claims,
+}
+
+impl AuthenticatedUser {
+    /// The user's ObjectId (hex), the token subject
+    pub fn user_id(&self) -> &str {
+        &self.claims.sub
+    }
+
+    /// Load the user record, for handlers that need more than the token's claims
+    pub async fn load_user(&self, db: &Database) -> Result<User, Error> {
+        db.collection::<User>("users")
+            .find_one(subject_filter(self.user_id()))
+            .await
+            .map_err(|e| ErrorInternalServerError(format!("Database error: {}", e)))?
+            .ok_or_else(|| ErrorUnauthorized("Unauthorized"))
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let validated = req.extensions().get::<Claims>().cloned();
+            let claims = match validated {
+                Some(claims) => claims,
+                None => validate_bearer_token(&req).await?,
+            };
+
+            if claims.is_client() {
+                return Err(ErrorUnauthorized("Client tokens are not bound to a user"));
+            }
+
+            Ok(AuthenticatedUser { claims })
+        })
+    }
+}
+
+async fn validate_bearer_token(req: &HttpRequest) -> Result<Claims, Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or_else(|| ErrorUnauthorized("Unauthorized"))?;
+
+    let claims = match req.app_data::<web::Data<ValidationPolicy>>() {
+        Some(policy) => validate_jwt(token, policy),
+        None => validate_jwt(token, &ValidationPolicy::default()),
+    }
+    .map_err(|_| ErrorUnauthorized("Unauthorized"))?;
+
+    let db = req
+        .app_data::<web::Data<Database>>()
+        .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+    if is_revoked(db, &claims).await.unwrap_or(true)
+        || is_outdated(db, &claims).await.unwrap_or(true)
+    {
+        return Err(ErrorUnauthorized("Unauthorized"));
+    }
+
+    // later extractors of the same request skip validation
+    req.extensions_mut().insert(claims.clone());
+    Ok(claims)
+}
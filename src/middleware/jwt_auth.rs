@@ -1,12 +1,36 @@
 use actix_web::{
-    Error, HttpMessage, HttpResponse,
+    Error, HttpMessage, ResponseError,
     body::EitherBody,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web,
 };
 use futures_util::future::{LocalBoxFuture, Ready, ok};
+use mongodb::Database;
 use std::rc::Rc;
 
-pub struct AuthMiddleware;
+use crate::utils::jwt::{TokenError, ValidationPolicy, validate_jwt};
+use crate::utils::oauth::OAuthError;
+use crate::utils::revocation::is_revoked;
+use crate::utils::token_version::is_outdated;
+
+/// Validates the Bearer token against a `ValidationPolicy` and puts its `Claims` in the
+/// request extensions. Rejected requests get `401 invalid_token` with the reason.
+/// `AuthMiddleware::default()` accepts access tokens only; pass another policy with
+/// `AuthMiddleware::with_policy(ValidationPolicy { leeway: 0, ..Default::default() })`.
+/// Revocation and the token version are checked in the `web::Data<Database>` app data;
+/// an app without it answers `500 server_error` to every valid token.
+#[derive(Default)]
+pub struct AuthMiddleware {
+    policy: Rc<ValidationPolicy>,
+}
+
+impl AuthMiddleware {
+    pub fn with_policy(policy: ValidationPolicy) -> Self {
+        AuthMiddleware {
+            policy: Rc::new(policy),
+        }
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
 where
@@ -22,12 +46,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(AuthMiddlewareMiddleware {
             service: Rc::new(service),
+            policy: Rc::clone(&self.policy),
         })
     }
 }
 
 pub struct AuthMiddlewareMiddleware<S> {
     service: Rc<S>,
+    policy: Rc<ValidationPolicy>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareMiddleware<S>
@@ -48,6 +74,7 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let service = Rc::clone(&self.service);
+        let policy = Rc::clone(&self.policy);
 
         Box::pin(async move {
             let token = req
@@ -58,30 +85,42 @@ where
                 .map(|s| s.to_string());
 
             println!("-> middleware/jwt_auth.rs -  token: {:?}", token);
-            if let Some(token) = token {
-                println!("AuthMiddleware received token: [{}]", token); // 실제 토큰 값 출
-                match crate::utils::jwt::verify_jwt(&token) {
-                    Ok(claims) => {
-                        req.extensions_mut().insert(claims);
-                        let res = service.call(req).await?;
-                        Ok(res.map_into_left_body())
-                    }
-                    Err(_) => {
-                        let response = req.into_response(
-                            HttpResponse::Unauthorized()
-                                .body("Invalid token")
-                                .map_into_right_body(),
-                        );
-                        Ok(response)
+            let claims = match token {
+                Some(token) => validate_jwt(&token, &policy),
+                None => Err(TokenError::Missing),
+            };
+
+            // tokens revoked by /logout, /revoke or an ended session, or issued before a password change
+            let claims = match claims {
+                Ok(claims) => {
+                    // without the database neither check can run, so nothing gets through
+                    let Some(db) = req.app_data::<web::Data<Database>>().cloned() else {
+                        let response =
+                            OAuthError::server_error("Database is not configured").error_response();
+                        return Ok(req.into_response(response.map_into_right_body()));
+                    };
+                    if is_revoked(&db, &claims).await.unwrap_or(true)
+                        || is_outdated(&db, &claims).await.unwrap_or(true)
+                    {
+                        Err(TokenError::Revoked)
+                    } else {
+                        Ok(claims)
                     }
                 }
-            } else {
-                let response = req.into_response(
-                    HttpResponse::Unauthorized()
-                        .body("Missing token")
-                        .map_into_right_body(),
-                );
-                Ok(response)
+                Err(e) => Err(e),
+            };
+
+            match claims {
+                Ok(claims) => {
+                    req.extensions_mut().insert(claims);
+                    let res = service.call(req).await?;
+                    Ok(res.map_into_left_body())
+                }
+                Err(e) => {
+                    println!("-> middleware/jwt_auth.rs - rejected: {}", e);
+                    let response = OAuthError::invalid_token(e.to_string()).error_response();
+                    Ok(req.into_response(response.map_into_right_body()))
+                }
             }
         })
     }
@@ -1 +1,4 @@
+pub mod authenticated_user;
 pub mod jwt_auth;
+pub mod require_role;
+pub mod require_scope;
@@ -0,0 +1,90 @@
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::rc::Rc;
+
+use crate::utils::jwt::Claims;
+use crate::utils::role::has_any_role;
+
+/// Restricts a route to users holding at least one of the given roles, others (and every
+/// token issued to an OAuth2 client) get `403 Forbidden`.
+/// Like `RequireScope` it must run inside `AuthMiddleware`:
+/// `web::scope("/admin").wrap(RequireRole::new(&[ROLE_ADMIN]))` within the `/api` scope.
+pub struct RequireRole {
+    roles: Rc<Vec<String>>,
+}
+
+impl RequireRole {
+    pub fn new(roles: &[&str]) -> Self {
+        RequireRole {
+            roles: Rc::new(roles.iter().map(|r| r.to_string()).collect()),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            roles: Rc::clone(&self.roles),
+        })
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    roles: Rc<Vec<String>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        ctx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let roles = Rc::clone(&self.roles);
+
+        Box::pin(async move {
+            // roles only count in first-party tokens, whatever a client's token claims
+            let allowed = req.extensions().get::<Claims>().is_some_and(|claims| {
+                claims.client_id.is_none() && has_any_role(&claims.roles, &roles)
+            });
+
+            if !allowed {
+                return Ok(req.into_response(
+                    HttpResponse::Forbidden()
+                        .body("Insufficient role")
+                        .map_into_right_body(),
+                ));
+            }
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use actix_web::{
+    Error, HttpMessage, ResponseError,
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::rc::Rc;
+
+use crate::utils::jwt::Claims;
+use crate::utils::oauth::OAuthError;
+use crate::utils::scope::has_scope;
+
+/// Rejects requests whose access token lacks any of the given scopes with
+/// `403 insufficient_scope`. Must run inside `AuthMiddleware`, which provides the claims:
+/// `web::resource("/x").wrap(RequireScope::new(&["profile"])).wrap(AuthMiddleware::default())` or
+/// wrapped around a route/resource inside an `AuthMiddleware` scope.
+pub struct RequireScope {
+    scopes: Rc<Vec<String>>,
+}
+
+impl RequireScope {
+    pub fn new(scopes: &[&str]) -> Self {
+        RequireScope {
+            scopes: Rc::new(scopes.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireScopeMiddleware {
+            service: Rc::new(service),
+            scopes: Rc::clone(&self.scopes),
+        })
+    }
+}
+
+pub struct RequireScopeMiddleware<S> {
+    service: Rc<S>,
+    scopes: Rc<Vec<String>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireScopeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        ctx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let scopes = Rc::clone(&self.scopes);
+
+        Box::pin(async move {
+            let granted = req
+                .extensions()
+                .get::<Claims>()
+                .and_then(|claims| claims.scope.clone());
+            let missing: Vec<&str> = scopes
+                .iter()
+                .filter(|scope| !has_scope(granted.as_deref(), scope))
+                .map(String::as_str)
+                .collect();
+
+            if !missing.is_empty() {
+                let error = OAuthError::insufficient_scope(format!(
+                    "Required scope: {}",
+                    missing.join(" ")
+                ));
+                return Ok(req.into_response(error.error_response().map_into_right_body()));
+            }
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,21 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Pending authorization code (stored in the `authorization_codes` collection).
+/// Only the SHA-256 hash of the code is persisted; documents expire through a TTL index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorizationCode {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub code_hash: String,
+    pub client_id: String,
+    pub user_email: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub code_challenge: String,
+    pub code_challenge_method: String,
+    pub nonce: Option<String>, // OpenID Connect nonce, echoed in the ID token
+    pub auth_time: i64,        // when the user authenticated (seconds since epoch)
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,94 @@
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::jwt::{ACCESS_TOKEN_MINUTES, REFRESH_TOKEN_MINUTES};
+
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+pub const SUPPORTED_GRANT_TYPES: [&str; 4] = [
+    "authorization_code",
+    "refresh_token",
+    "client_credentials",
+    DEVICE_CODE_GRANT_TYPE,
+];
+
+/// OAuth2 client application (stored in the `clients` collection)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Client {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub client_id: String,
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret_hash: Option<String>, // Argon2 hash, None for public clients
+    #[serde(default = "default_grant_types")]
+    pub grant_types: Vec<String>,
+    #[serde(default = "default_response_types")]
+    pub response_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // space-delimited scopes the client may request
+    #[serde(default = "default_auth_method")]
+    pub token_endpoint_auth_method: String, // "client_secret_basic", "client_secret_post" or "none"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token_lifetime: Option<i64>, // seconds, overrides ACCESS_TOKEN_MINUTES
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token_lifetime: Option<i64>, // seconds, overrides REFRESH_TOKEN_MINUTES
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_access_token_hash: Option<String>, // RFC 7592, SHA-256 (utils::token)
+    #[serde(default)]
+    pub client_id_issued_at: i64,
+}
+
+fn default_grant_types() -> Vec<String> {
+    vec![
+        "authorization_code".to_string(),
+        "refresh_token".to_string(),
+    ]
+}
+
+fn default_response_types() -> Vec<String> {
+    vec!["code".to_string()]
+}
+
+fn default_auth_method() -> String {
+    "client_secret_basic".to_string()
+}
+
+impl Client {
+    /// Confidential clients hold a secret and must authenticate at the token endpoint
+    pub fn is_confidential(&self) -> bool {
+        self.client_secret_hash.is_some()
+    }
+
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|g| g == grant_type)
+    }
+
+    pub fn access_token_ttl(&self) -> Duration {
+        self.access_token_lifetime
+            .map(Duration::seconds)
+            .unwrap_or_else(|| Duration::minutes(ACCESS_TOKEN_MINUTES))
+    }
+
+    pub fn refresh_token_ttl(&self) -> Duration {
+        self.refresh_token_lifetime
+            .map(Duration::seconds)
+            .unwrap_or_else(|| Duration::minutes(REFRESH_TOKEN_MINUTES))
+    }
+
+    /// Exact-match redirect URI check (RFC 6749 section 3.1.2.3)
+    pub fn resolve_redirect_uri(&self, requested: Option<&str>) -> Option<String> {
+        match requested {
+            Some(uri) => self
+                .redirect_uris
+                .iter()
+                .find(|registered| registered.as_str() == uri)
+                .cloned(),
+            // redirect_uri may be omitted only when exactly one is registered
+            None if self.redirect_uris.len() == 1 => self.redirect_uris.first().cloned(),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Pending device authorization (RFC 8628, stored in the `device_codes` collection).
+/// The device_code is stored as a SHA-256 hash; the user_code without its dash.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceCode {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub device_code_hash: String,
+    pub user_code: String,
+    pub client_id: String,
+    pub scope: Option<String>,
+    pub status: String,             // "pending", "approved" or "denied"
+    pub user_email: Option<String>, // set once a user approves
+    pub auth_time: Option<i64>,
+    pub interval: i64, // minimum seconds between polls, raised on slow_down
+    pub last_polled_at: Option<DateTime>,
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,16 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Pending email verification (stored in the `email_verifications` collection).
+/// Only the SHA-256 hash of the random token in the emailed link is persisted: using the link
+/// deletes the record, and a resend replaces it. Removed by a TTL index once expired.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub token_hash: String,
+    pub user_id: ObjectId,
+    pub email: String, // address being verified
+    pub expires_at: DateTime,
+}
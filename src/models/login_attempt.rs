@@ -0,0 +1,18 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Consecutive failed logins for an email address (stored in the `login_attempts` collection).
+/// Kept per attempted address whether or not an account has it, so a lockout looks the same
+/// for both. Deleted by a complete login, an administrator unlock, or a TTL index once
+/// `expires_at` passes without another failure.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginAttempt {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub email: String,
+    pub failed_logins: i64, // consecutive failed passwords and second factors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<DateTime>,
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,19 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Login waiting for a second factor (stored in the `mfa_challenges` collection).
+/// Created once the password checked out; only the SHA-256 hash of the challenge token
+/// is persisted. Deleted on success, after too many wrong codes, or by a TTL index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaChallenge {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub token_hash: String,
+    pub user_id: ObjectId,
+    pub scope: String, // scope granted by the password step
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub attempts: i32, // wrong codes so far
+    pub expires_at: DateTime,
+}
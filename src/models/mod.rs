@@ -1 +1,14 @@
+pub mod authorization_code;
+pub mod client;
+pub mod device_code;
+pub mod email_verification;
+pub mod login_attempt;
+pub mod mfa_challenge;
+pub mod password_reset;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod security_event;
+pub mod session;
 pub mod user;
+pub mod webauthn_challenge;
+pub mod webauthn_credential;
@@ -0,0 +1,15 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Pending password reset (stored in the `password_resets` collection).
+/// Only the SHA-256 hash of the emailed token is persisted; it is deleted on use and
+/// otherwise expires through a TTL index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordReset {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub token_hash: String,
+    pub user_id: ObjectId,
+    pub expires_at: DateTime,
+}
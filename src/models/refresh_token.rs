@@ -0,0 +1,19 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Issued refresh token (stored in the `refresh_tokens` collection), looked up by its `jti`.
+/// Every refresh rotates the token: the presented one is marked "rotated" and a child with
+/// the same `family_id` is issued. Records are removed by a TTL index once they expire.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub jti: String,
+    pub family_id: String,
+    pub user_id: ObjectId,
+    pub client_id: Option<String>,
+    pub status: String, // "active", "rotated" or "revoked"
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+}
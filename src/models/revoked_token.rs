@@ -0,0 +1,17 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Revocation entry (stored in the `revoked_tokens` collection) for a single token (`jti`)
+/// or for every token of a login session (`sid`).
+/// Kept until the tokens would have expired anyway, then removed by a TTL index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub expires_at: DateTime,
+}
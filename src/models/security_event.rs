@@ -0,0 +1,15 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Audit record of a security-relevant event (stored in the `security_events` collection)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub event_type: String, // e.g. "refresh_token_reuse"
+    pub user_email: Option<String>,
+    pub client_id: Option<String>,
+    pub details: String,
+    pub created_at: DateTime,
+}
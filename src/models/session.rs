@@ -0,0 +1,22 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Login session (stored in the `sessions` collection).
+/// `session_id` doubles as the refresh token family id; the session lasts as long as its
+/// current refresh token and is removed by a TTL index afterwards.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub session_id: String,
+    pub user_id: ObjectId,
+    pub client_id: Option<String>, // OAuth2 client, None for first-party logins
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub refresh_token_jti: Option<String>, // current refresh token of the family
+    pub created_at: DateTime,
+    pub last_used_at: DateTime,
+    pub expires_at: DateTime,
+}
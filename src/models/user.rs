@@ -1,6 +1,13 @@
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, Document, doc};
 use serde::{Deserialize, Serialize};
 
+use crate::models::webauthn_credential::WebAuthnCredential;
+use crate::utils::jwt::Claims;
+use crate::utils::role::default_user_roles;
+use crate::utils::scope::default_user_scopes;
+use crate::utils::token_version::is_current_version;
+
 // #[derive(Debug, Serialize, Deserialize)]
 // pub struct User {
 //     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -17,5 +24,67 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub password: String,
-    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(default = "default_user_scopes")]
+    pub scopes: Vec<String>, // scopes this user may grant to tokens
+    #[serde(default = "default_user_roles")]
+    pub roles: Vec<String>, // "user", "admin"
+    #[serde(default)]
+    pub token_version: i64, // bumped to sign out every token issued before
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_secret: Option<String>, // base32 TOTP secret, set once enrollment is confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_pending_secret: Option<String>, // secret waiting for its first code
+    #[serde(default)]
+    pub totp_last_step: i64, // last time step a code was accepted for, codes can't be replayed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery_codes: Vec<String>, // argon2 hashes of the unused MFA recovery codes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webauthn_credentials: Vec<WebAuthnCredential>, // passkeys and security keys
+}
+
+impl User {
+    /// Token subject (`sub`): the ObjectId in hex, which stays stable if the email changes
+    pub fn subject(&self) -> String {
+        self.id.map(|id| id.to_hex()).unwrap_or_default()
+    }
+
+    /// Whether login needs a second factor
+    pub fn mfa_enabled(&self) -> bool {
+        self.totp_secret.is_some() || !self.webauthn_credentials.is_empty()
+    }
+
+    /// Whether the user has a TOTP authenticator app
+    pub fn totp_enabled(&self) -> bool {
+        self.totp_secret.is_some()
+    }
+
+    /// Second factors the user can answer an MFA challenge with
+    pub fn mfa_methods(&self) -> Vec<String> {
+        let mut methods = Vec::new();
+        if self.totp_secret.is_some() {
+            methods.push("totp".to_string());
+        }
+        if !self.webauthn_credentials.is_empty() {
+            methods.push("webauthn".to_string());
+        }
+        if !self.recovery_codes.is_empty() {
+            methods.push("recovery_code".to_string());
+        }
+        methods
+    }
+
+    /// Whether the token was issued after the last password change (see utils::token_version)
+    pub fn token_is_current(&self, claims: &Claims) -> bool {
+        is_current_version(claims, self.token_version)
+    }
+}
+
+/// Query for the user a token subject names; a malformed subject matches no user
+pub fn subject_filter(sub: &str) -> Document {
+    match ObjectId::parse_str(sub) {
+        Ok(id) => doc! { "_id": id },
+        Err(_) => doc! { "_id": Bson::Null },
+    }
 }
@@ -0,0 +1,16 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Pending WebAuthn ceremony (stored in the `webauthn_challenges` collection).
+/// Only the SHA-256 hash of the challenge is persisted; it is deleted when the browser's
+/// answer comes back and otherwise expires through a TTL index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebAuthnChallenge {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub challenge_hash: String,
+    pub ceremony: String,          // "registration" or "authentication"
+    pub user_id: Option<ObjectId>, // None for a passwordless login, the user is found by credential
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,16 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Passkey or security key registered by a user (embedded in `users.webauthn_credentials`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebAuthnCredential {
+    pub credential_id: String, // base64url, as the browser reports it
+    pub public_key: String,    // base64url COSE_Key
+    pub algorithm: i64,        // COSE algorithm, e.g. -7 for ES256
+    pub sign_count: i64,       // last signature counter, to spot cloned authenticators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub created_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime>,
+}
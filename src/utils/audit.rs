@@ -0,0 +1,32 @@
+use mongodb::Database;
+use mongodb::bson::DateTime;
+
+use crate::models::security_event::SecurityEvent;
+
+/// Append an event to the `security_events` audit trail.
+/// Failures are logged and never fail the request that triggered the event.
+pub async fn record_security_event(
+    db: &Database,
+    event_type: &str,
+    user_email: Option<&str>,
+    client_id: Option<&str>,
+    details: impl Into<String>,
+) {
+    let event = SecurityEvent {
+        id: None,
+        event_type: event_type.to_string(),
+        user_email: user_email.map(str::to_owned),
+        client_id: client_id.map(str::to_owned),
+        details: details.into(),
+        created_at: DateTime::now(),
+    };
+    println!("-> utils/audit.rs - security event: {:?}", event);
+
+    if let Err(e) = db
+        .collection::<SecurityEvent>("security_events")
+        .insert_one(&event)
+        .await
+    {
+        eprintln!("Failed to record security event {:?}: {}", event, e);
+    }
+}
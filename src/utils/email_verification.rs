@@ -0,0 +1,77 @@
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+
+use crate::config::base_url;
+use crate::models::email_verification::EmailVerification;
+use crate::models::user::User;
+use crate::utils::mailer::{Email, Mailer};
+use crate::utils::token::{generate_token, hash_token};
+
+pub const EMAIL_VERIFICATION_HOURS: i64 = 24;
+
+/// Email the user a new verification link, replacing any link sent before
+pub async fn send_verification_email(
+    db: &Database,
+    mailer: &dyn Mailer,
+    user: &User,
+) -> Result<(), String> {
+    let user_id = user.id.ok_or("User has no id")?;
+    let token = generate_token();
+
+    let collection = db.collection::<EmailVerification>("email_verifications");
+    collection
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    collection
+        .insert_one(EmailVerification {
+            id: None,
+            token_hash: hash_token(&token),
+            user_id,
+            email: user.email.clone(),
+            expires_at: DateTime::from_millis(
+                (Utc::now() + Duration::hours(EMAIL_VERIFICATION_HOURS)).timestamp_millis(),
+            ),
+        })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let link = format!("{}/verify-email?token={}", base_url(), token);
+    mailer
+        .send(&Email {
+            to: user.email.clone(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Hello {},\n\nPlease confirm your email address by opening this link:\n{}\n\nThe link expires in {} hours.\n",
+                user.username, link, EMAIL_VERIFICATION_HOURS
+            ),
+        })
+        .await
+}
+
+/// Use a verification token once and mark the address verified.
+/// Returns false for unknown, expired, used or superseded tokens.
+pub async fn verify_email_token(db: &Database, token: &str) -> Result<bool, MongoError> {
+    let Some(verification) = db
+        .collection::<EmailVerification>("email_verifications")
+        .find_one_and_delete(
+            doc! { "token_hash": hash_token(token), "expires_at": { "$gt": DateTime::now() } },
+        )
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    // the address must not have changed since the link was sent
+    let result = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": verification.user_id, "email": &verification.email },
+            doc! { "$set": { "email_verified": true } },
+        )
+        .await?;
+
+    Ok(result.matched_count == 1)
+}
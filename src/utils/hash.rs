@@ -2,6 +2,7 @@ use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
+use std::sync::OnceLock;
 
 pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
     let salt = SaltString::generate(&mut OsRng); // OsRng는 Zero-Cost Type이므로 메모리 할당 없이 사용 가능
@@ -19,3 +20,19 @@ pub fn verify_password(hash: &str, password: &str) -> Result<bool, argon2::passw
         .verify_password(password.as_bytes(), &parse_hash)
         .is_ok())
 }
+
+/// Verify a password against the account's hash. Without an account a dummy hash is
+/// checked instead, so an unknown email takes as long to refuse as a wrong password.
+pub fn verify_account_password(hash: Option<&str>, password: &str) -> bool {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    match hash {
+        Some(hash) => verify_password(hash, password).unwrap_or(false),
+        None => {
+            let dummy = DUMMY_HASH.get_or_init(|| {
+                hash_password("dummy password").expect("Failed to hash the dummy password")
+            });
+            let _ = verify_password(dummy, password);
+            false
+        }
+    }
+}
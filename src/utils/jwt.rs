@@ -1,86 +1,340 @@
-use actix_web::Error;
-use actix_web::error::ErrorUnauthorized;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use chrono::{Duration, Utc};
 use jsonwebtoken::errors::Error as JwtError;
-use jsonwebtoken::{
-    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation, decode, encode,
-};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, Header, Validation, decode, decode_header, encode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
+use std::fmt;
+use std::str::FromStr;
 use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::config::{audience, issuer};
+use crate::utils::keys::{signing_key, verification_key};
 
 // const SECRET: &[u8] = b"your-secret_key_change_me"; // 사용되지 않으므로 주석 처리 또는 삭제
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_MINUTES: i64 = 60 * 24 * 7;
+pub const ID_TOKEN_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[schema(example = json!({
-    "sub": "user@example.com",
+    "sub": "665f1c2e8b3e4a2d9c0b1a2f",
     "exp": 1678886400,
-    "token_type": "access"
+    "iss": "http://localhost:8080",
+    "aud": "http://localhost:8080",
+    "iat": 1678885500,
+    "nbf": 1678885500,
+    "jti": "0f8fad5b-d9cb-469f-a165-70867728950e",
+    "token_type": "access",
+    "scope": "openid profile",
+    "client_id": "my-client",
+    "sub_type": "user",
+    "roles": ["user"],
+    "ver": 0
 }))]
 pub struct Claims {
-    pub sub: String,
+    pub sub: String, // user ObjectId (hex), or the client_id for client tokens
     pub exp: usize,
+    // iss, aud, iat and nbf are filled in by create_jwt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
     pub token_type: String, // "access" or "refresh"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // space-delimited OAuth2 scopes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>, // OAuth2 client the token was issued to
+    #[serde(default = "default_sub_type")]
+    pub sub_type: String, // "user" (sub is a user) or "client" (client_credentials, sub is the client_id)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>, // token id, for revocation and refresh token rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>, // login session (refresh token family) the token belongs to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>, // the user's roles, first-party access tokens only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ver: Option<i64>, // the user's token_version at issue time
+}
+
+fn default_sub_type() -> String {
+    "user".to_string()
+}
+
+impl Claims {
+    pub fn new(sub: &str, lifetime: Duration, token_type: &str) -> Self {
+        let expiration = Utc::now()
+            .checked_add_signed(lifetime)
+            .expect("valid timestamp")
+            .timestamp() as usize;
+
+        Claims {
+            sub: sub.to_string(),
+            exp: expiration,
+            iss: None,
+            aud: None,
+            iat: None,
+            nbf: None,
+            token_type: token_type.to_owned(),
+            scope: None,
+            client_id: None,
+            sub_type: default_sub_type(),
+            jti: Some(Uuid::new_v4().to_string()),
+            sid: None,
+            roles: Vec::new(),
+            ver: None,
+        }
+    }
+
+    /// Token issued to a client on its own behalf, without a user
+    pub fn is_client(&self) -> bool {
+        self.sub_type == "client"
+    }
+}
+
+/// OpenID Connect ID token claims (OIDC Core section 2)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String, // client_id of the relying party
+    pub exp: usize,
+    pub iat: usize,
+    pub auth_time: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at_hash: Option<String>,
+}
+
+/// OIDC `at_hash`: base64url of the left half of the access token's SHA-256 hash
+pub fn at_hash(access_token: &str) -> String {
+    let digest = Sha256::digest(access_token.as_bytes());
+    URL_SAFE_NO_PAD.encode(&digest[..digest.len() / 2])
 }
 
 //-- added Token Refrech Login
 //   + On login: receive both access and refresh tokens
 //   + When the access token expires, send the refresh token to get a new access token
-pub fn create_jwt(user_id: &str, minutes: i64, token_type: &str) -> Result<String, JwtError> {
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::minutes(minutes))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp: expiration,
-        token_type: token_type.to_owned(),
+//   + iss, aud, iat, nbf and jti are added from the configuration unless the claims set them
+pub fn create_jwt<T: Serialize>(claims: &T) -> Result<String, JwtError> {
+    let key = signing_key();
+    let header = Header {
+        kid: Some(key.kid.clone()),
+        ..Header::new(key.algorithm)
     };
 
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let mut claims = serde_json::to_value(claims)?;
+    if let Some(claims) = claims.as_object_mut() {
+        let now = Utc::now().timestamp();
+        claims.entry("iss").or_insert_with(|| issuer().into());
+        claims.entry("aud").or_insert_with(|| audience().into());
+        claims.entry("iat").or_insert_with(|| now.into());
+        claims.entry("nbf").or_insert_with(|| now.into());
+        claims
+            .entry("jti")
+            .or_insert_with(|| Uuid::new_v4().to_string().into());
+    }
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+    encode(&header, &claims, &key.encoding)
 }
 
-pub fn verify_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+/// Verify a token of any type: signature, `exp`/`nbf`, `iss`, `aud` and the required claims
+pub fn verify_jwt(token: &str) -> Result<Claims, TokenError> {
     println!("-> utils/jwt.rs - verify_jwt - token: {:?}", token);
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-    match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    ) {
-        Ok(token_data) => {
-            // println!("utils/jwt.rs - verify_jwt - token_data: {:?}", token_data);
-            println!(
-                "-> utils/jwt.rs - verify_jwt - token_data.claims: {:?}",
-                token_data.claims
-            );
-            Ok(token_data.claims)
+
+    let policy = ValidationPolicy {
+        token_type: None,
+        ..Default::default()
+    };
+    match validate_jwt(token, &policy) {
+        Ok(claims) => {
+            println!("-> utils/jwt.rs - verify_jwt - claims: {:?}", claims);
+            Ok(claims)
         }
         Err(e) => {
-            eprintln!("Error decoding JWT: {:?}", e);
-            eprintln!("Error kind: {:?}", e.kind());
+            eprintln!("Error verifying JWT: {}", e);
             Err(e)
         }
     }
 }
 
-pub fn extract_email_from_jwt(token: &str) -> Result<String, Error> {
-    let secret = env::var("JWT_SECRET").map_err(|_| ErrorUnauthorized("Missing JWT_SECRET"))?;
+/// Why a token was rejected, reported to the client as the `invalid_token` description
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenError {
+    Missing,
+    Malformed,
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    InvalidIssuer,
+    InvalidAudience,
+    MissingClaim(String),
+    AlgorithmNotAllowed,
+    WrongTokenType,
+    Revoked,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Missing => write!(f, "Missing token"),
+            TokenError::Malformed => write!(f, "Malformed token"),
+            TokenError::InvalidSignature => write!(f, "Invalid token signature"),
+            TokenError::Expired => write!(f, "Token expired"),
+            TokenError::NotYetValid => write!(f, "Token not yet valid"),
+            TokenError::InvalidIssuer => write!(f, "Invalid token issuer"),
+            TokenError::InvalidAudience => write!(f, "Invalid token audience"),
+            TokenError::MissingClaim(claim) => write!(f, "Missing required claim: {}", claim),
+            TokenError::AlgorithmNotAllowed => write!(f, "Token algorithm not allowed"),
+            TokenError::WrongTokenType => write!(f, "Wrong token type"),
+            TokenError::Revoked => write!(f, "Token revoked"),
+        }
+    }
+}
 
-    let token_data: TokenData<Claims> = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    )
-    .map_err(|_| ErrorUnauthorized("Invalid or expired token"))?;
+impl From<JwtError> for TokenError {
+    fn from(e: JwtError) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => TokenError::Expired,
+            ErrorKind::ImmatureSignature => TokenError::NotYetValid,
+            ErrorKind::InvalidIssuer => TokenError::InvalidIssuer,
+            ErrorKind::InvalidAudience => TokenError::InvalidAudience,
+            ErrorKind::MissingRequiredClaim(claim) => TokenError::MissingClaim(claim.clone()),
+            ErrorKind::InvalidAlgorithm => TokenError::AlgorithmNotAllowed,
+            ErrorKind::InvalidSignature => TokenError::InvalidSignature,
+            _ => TokenError::Malformed,
+        }
+    }
+}
+
+/// What a token must satisfy, on top of a valid signature from a known key.
+/// The default accepts access tokens from this issuer (`JWT_ISSUER`) for this audience
+/// (`JWT_AUDIENCE`) carrying every registered claim we issue, with 60 seconds of clock skew
+/// and any algorithm of the key set.
+#[derive(Debug, Clone)]
+pub struct ValidationPolicy {
+    pub token_type: Option<String>,    // None accepts any token_type
+    pub issuer: Option<String>,        // expected `iss`
+    pub audience: Option<Vec<String>>, // accepted `aud` values
+    pub leeway: u64,                   // seconds of clock skew for exp and nbf
+    pub required_claims: Vec<String>,
+    pub algorithms: Option<Vec<Algorithm>>, // None allows every algorithm of the key set
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        ValidationPolicy {
+            token_type: Some("access".to_string()),
+            issuer: Some(issuer()),
+            audience: Some(vec![audience()]),
+            leeway: 60,
+            required_claims: ["exp", "sub", "iat", "nbf", "jti"]
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+            algorithms: None,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Default policy adjusted by `JWT_LEEWAY_SECONDS` and `JWT_ALLOWED_ALGORITHMS`
+    /// (comma-separated). Panics on invalid values.
+    pub fn from_env() -> Self {
+        let mut policy = ValidationPolicy::default();
+        if let Ok(leeway) = env::var("JWT_LEEWAY_SECONDS") {
+            policy.leeway = leeway
+                .parse()
+                .expect("JWT_LEEWAY_SECONDS must be a number of seconds");
+        }
+        if let Ok(names) = env::var("JWT_ALLOWED_ALGORITHMS") {
+            let algorithms = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    Algorithm::from_str(name).unwrap_or_else(|_| {
+                        panic!("Unknown algorithm in JWT_ALLOWED_ALGORITHMS: {}", name)
+                    })
+                })
+                .collect();
+            policy.algorithms = Some(algorithms);
+        }
+        policy
+    }
+}
+
+// claims jsonwebtoken checks for presence itself
+const SPEC_CLAIMS: [&str; 5] = ["exp", "nbf", "aud", "iss", "sub"];
+
+/// Decode a token and check it against a validation policy
+pub fn validate_jwt(token: &str, policy: &ValidationPolicy) -> Result<Claims, TokenError> {
+    let header = decode_header(token)?;
+    if policy
+        .algorithms
+        .as_ref()
+        .is_some_and(|allowed| !allowed.contains(&header.alg))
+    {
+        return Err(TokenError::AlgorithmNotAllowed);
+    }
+    let key = verification_key(header.kid.as_deref()).ok_or(TokenError::InvalidSignature)?;
+
+    let mut validation = Validation::new(key.algorithm);
+    validation.leeway = policy.leeway;
+    validation.validate_nbf = true;
+    match &policy.issuer {
+        Some(issuer) => validation.set_issuer(&[issuer]),
+        None => validation.iss = None,
+    }
+    match &policy.audience {
+        Some(audience) => validation.set_audience(audience),
+        None => validation.validate_aud = false,
+    }
+    let mut spec_claims: Vec<&str> = policy
+        .required_claims
+        .iter()
+        .map(String::as_str)
+        .filter(|claim| SPEC_CLAIMS.contains(claim))
+        .collect();
+    // an expected issuer or audience can't be satisfied by leaving the claim out
+    if policy.issuer.is_some() {
+        spec_claims.push("iss");
+    }
+    if policy.audience.is_some() {
+        spec_claims.push("aud");
+    }
+    validation.set_required_spec_claims(&spec_claims);
+
+    let claims = decode::<Claims>(token, &key.decoding, &validation)?.claims;
+
+    // the remaining required claims are checked on the decoded token
+    let present = serde_json::to_value(&claims).unwrap_or_default();
+    if let Some(missing) = policy
+        .required_claims
+        .iter()
+        .filter(|claim| !SPEC_CLAIMS.contains(&claim.as_str()))
+        .find(|claim| present.get(claim.as_str()).is_none_or(|v| v.is_null()))
+    {
+        return Err(TokenError::MissingClaim(missing.clone()));
+    }
+
+    if policy
+        .token_type
+        .as_ref()
+        .is_some_and(|token_type| *token_type != claims.token_type)
+    {
+        return Err(TokenError::WrongTokenType);
+    }
 
-    Ok(token_data.claims.sub)
+    Ok(claims)
 }
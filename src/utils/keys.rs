@@ -0,0 +1,349 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::rand::SystemRandom;
+use ring::signature::{
+    ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair,
+    RsaPublicKeyComponents,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::{Arc, OnceLock, RwLock};
+use utoipa::ToSchema;
+
+/// Public half of a signing key as a JSON Web Key (RFC 7517)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Jwk {
+    pub kty: String, // "RSA", "EC" or "OKP"
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub alg: String,
+    pub kid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>, // RSA modulus
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>, // RSA public exponent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>, // "P-256" or "Ed25519"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+/// JWK Set document served at `/.well-known/jwks.json`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// Key used to sign and verify the tokens this server issues
+pub struct SigningKey {
+    pub algorithm: Algorithm,
+    pub kid: String,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    pub jwk: Option<Jwk>, // None for HS256, the shared secret is never published
+}
+
+/// Keys trusted for verification; one of them is active for signing
+pub struct KeySet {
+    pub active: Arc<SigningKey>,
+    pub keys: Vec<Arc<SigningKey>>,
+}
+
+/// `JWT_KEYS_FILE` layout: `{"active": "<kid>", "keys": [...]}`
+#[derive(Debug, Deserialize)]
+struct KeysFile {
+    active: String,
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyEntry {
+    kid: Option<String>, // required for HS256, defaults to the JWK thumbprint otherwise
+    alg: String,
+    private_key_path: Option<String>, // RS256, ES256, EdDSA
+    secret: Option<String>,           // HS256
+}
+
+static KEY_SET: OnceLock<RwLock<Arc<KeySet>>> = OnceLock::new();
+
+fn key_set_lock() -> &'static RwLock<Arc<KeySet>> {
+    KEY_SET.get_or_init(|| {
+        let key_set =
+            load_key_set().unwrap_or_else(|e| panic!("Failed to load JWT signing keys: {}", e));
+        RwLock::new(Arc::new(key_set))
+    })
+}
+
+/// Current key set, loaded from the environment on first use
+pub fn key_set() -> Arc<KeySet> {
+    key_set_lock().read().unwrap().clone()
+}
+
+/// The active key, used to sign new tokens
+pub fn signing_key() -> Arc<SigningKey> {
+    key_set().active.clone()
+}
+
+/// Key for verifying a token header's `kid`.
+/// Tokens without a `kid` predate key rotation and are checked against the active key.
+pub fn verification_key(kid: Option<&str>) -> Option<Arc<SigningKey>> {
+    let key_set = key_set();
+    match kid {
+        Some(kid) => key_set.keys.iter().find(|key| key.kid == kid).cloned(),
+        None => Some(key_set.active.clone()),
+    }
+}
+
+/// Re-read the key configuration; the previous key set stays in use if it fails
+pub fn reload_keys() -> Result<(), String> {
+    let key_set = load_key_set()?;
+    println!(
+        "-> utils/keys.rs - reload_keys - active kid: {:?}, {} key(s)",
+        key_set.active.kid,
+        key_set.keys.len()
+    );
+    *key_set_lock().write().unwrap() = Arc::new(key_set);
+    Ok(())
+}
+
+/// Public keys that verifiers should trust
+pub fn public_jwks() -> Jwks {
+    Jwks {
+        keys: key_set()
+            .keys
+            .iter()
+            .filter_map(|key| key.jwk.clone())
+            .collect(),
+    }
+}
+
+/// Signing algorithms in use, for the discovery document
+pub fn algorithms() -> Vec<String> {
+    let mut algorithms: Vec<String> = Vec::new();
+    for key in &key_set().keys {
+        let name = algorithm_name(key.algorithm).to_string();
+        if !algorithms.contains(&name) {
+            algorithms.push(name);
+        }
+    }
+    algorithms
+}
+
+/// Load the key set from `JWT_KEYS_FILE`, or a single key from the environment.
+///
+/// Without a keys file, `JWT_ALGORITHM` selects HS256 (default, `JWT_SECRET`), RS256,
+/// ES256 or EdDSA; the asymmetric algorithms read a PEM private key (PKCS#8, or PKCS#1
+/// for RSA) from `JWT_PRIVATE_KEY_PATH`.
+fn load_key_set() -> Result<KeySet, String> {
+    let (entries, active) = match env::var("JWT_KEYS_FILE") {
+        Ok(path) => {
+            let contents = std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let file: KeysFile =
+                serde_json::from_slice(&contents).map_err(|e| format!("{}: {}", path, e))?;
+            (file.keys, Some(file.active))
+        }
+        Err(_) => {
+            let alg = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+            let entry = KeyEntry {
+                kid: (alg == "HS256").then(|| "default".to_string()),
+                private_key_path: env::var("JWT_PRIVATE_KEY_PATH").ok(),
+                secret: env::var("JWT_SECRET").ok(),
+                alg,
+            };
+            (vec![entry], None)
+        }
+    };
+
+    let mut keys: Vec<Arc<SigningKey>> = Vec::new();
+    for entry in entries {
+        let key = load_key(entry)?;
+        if keys.iter().any(|k| k.kid == key.kid) {
+            return Err(format!("Duplicate kid: {}", key.kid));
+        }
+        keys.push(Arc::new(key));
+    }
+
+    let active = match active {
+        Some(kid) => keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .cloned()
+            .ok_or_else(|| format!("Active kid {} is not in the key set", kid))?,
+        None => keys.first().cloned().ok_or("No signing keys configured")?,
+    };
+
+    Ok(KeySet { active, keys })
+}
+
+fn load_key(entry: KeyEntry) -> Result<SigningKey, String> {
+    match entry.alg.as_str() {
+        "HS256" => {
+            let secret = entry
+                .secret
+                .ok_or("HS256 keys need a secret (JWT_SECRET)")?;
+            let kid = entry.kid.ok_or("HS256 keys need a kid")?;
+            Ok(SigningKey {
+                algorithm: Algorithm::HS256,
+                kid,
+                encoding: EncodingKey::from_secret(secret.as_bytes()),
+                decoding: DecodingKey::from_secret(secret.as_bytes()),
+                jwk: None,
+            })
+        }
+        "RS256" | "ES256" | "EdDSA" => {
+            let path = entry.private_key_path.ok_or_else(|| {
+                format!(
+                    "{} keys need a private key path (JWT_PRIVATE_KEY_PATH)",
+                    entry.alg
+                )
+            })?;
+            let pem = std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let mut key = key_from_pem(&entry.alg, &pem)?;
+            if let Some(kid) = entry.kid {
+                key.kid = kid.clone();
+                if let Some(jwk) = key.jwk.as_mut() {
+                    jwk.kid = kid;
+                }
+            }
+            Ok(key)
+        }
+        other => Err(format!("Unsupported JWT algorithm: {}", other)),
+    }
+}
+
+/// Build an asymmetric signing key and its public JWK from a PEM private key
+fn key_from_pem(algorithm: &str, pem_bytes: &[u8]) -> Result<SigningKey, String> {
+    let pem = pem::parse(pem_bytes).map_err(|e| format!("Invalid PEM: {}", e))?;
+    let der = pem.contents();
+
+    let (algorithm, mut jwk) = match algorithm {
+        "RS256" => {
+            // PKCS#1 ("RSA PRIVATE KEY") or PKCS#8 ("PRIVATE KEY")
+            let key_pair = match pem.tag() {
+                "RSA PRIVATE KEY" => RsaKeyPair::from_der(der),
+                _ => RsaKeyPair::from_pkcs8(der),
+            }
+            .map_err(|e| format!("Invalid RSA private key: {}", e))?;
+            let public = RsaPublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+            (Algorithm::RS256, rsa_jwk(&public.n, &public.e))
+        }
+        "ES256" => {
+            let key_pair = EcdsaKeyPair::from_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                der,
+                &SystemRandom::new(),
+            )
+            .map_err(|e| format!("Invalid P-256 private key (PKCS#8 expected): {}", e))?;
+            // uncompressed point: 0x04 || x || y
+            let point = key_pair.public_key().as_ref();
+            let (x, y) = point[1..].split_at(32);
+            let jwk = Jwk {
+                crv: Some("P-256".to_string()),
+                x: Some(URL_SAFE_NO_PAD.encode(x)),
+                y: Some(URL_SAFE_NO_PAD.encode(y)),
+                ..empty_jwk("EC")
+            };
+            (Algorithm::ES256, jwk)
+        }
+        "EdDSA" => {
+            let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+                .map_err(|e| format!("Invalid Ed25519 private key: {}", e))?;
+            let jwk = Jwk {
+                crv: Some("Ed25519".to_string()),
+                x: Some(URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref())),
+                ..empty_jwk("OKP")
+            };
+            (Algorithm::EdDSA, jwk)
+        }
+        other => return Err(format!("Unsupported asymmetric algorithm: {}", other)),
+    };
+
+    let encoding = match algorithm {
+        Algorithm::RS256 => EncodingKey::from_rsa_pem(pem_bytes),
+        Algorithm::ES256 => EncodingKey::from_ec_pem(pem_bytes),
+        _ => EncodingKey::from_ed_pem(pem_bytes),
+    }
+    .map_err(|e| format!("Invalid private key: {}", e))?;
+
+    jwk.alg = algorithm_name(algorithm).to_string();
+    jwk.kid = thumbprint(&jwk);
+    let decoding = decoding_key(&jwk)?;
+
+    Ok(SigningKey {
+        algorithm,
+        kid: jwk.kid.clone(),
+        encoding,
+        decoding,
+        jwk: Some(jwk),
+    })
+}
+
+fn rsa_jwk(n: &[u8], e: &[u8]) -> Jwk {
+    Jwk {
+        n: Some(URL_SAFE_NO_PAD.encode(n)),
+        e: Some(URL_SAFE_NO_PAD.encode(e)),
+        ..empty_jwk("RSA")
+    }
+}
+
+fn empty_jwk(kty: &str) -> Jwk {
+    Jwk {
+        kty: kty.to_string(),
+        use_: "sig".to_string(),
+        alg: String::new(),
+        kid: String::new(),
+        n: None,
+        e: None,
+        crv: None,
+        x: None,
+        y: None,
+    }
+}
+
+fn decoding_key(jwk: &Jwk) -> Result<DecodingKey, String> {
+    let field = |value: &Option<String>| value.clone().unwrap_or_default();
+    match jwk.kty.as_str() {
+        "RSA" => DecodingKey::from_rsa_components(&field(&jwk.n), &field(&jwk.e)),
+        "EC" => DecodingKey::from_ec_components(&field(&jwk.x), &field(&jwk.y)),
+        _ => DecodingKey::from_ed_components(&field(&jwk.x)),
+    }
+    .map_err(|e| format!("Invalid public key: {}", e))
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::RS256 => "RS256",
+        Algorithm::ES256 => "ES256",
+        Algorithm::EdDSA => "EdDSA",
+        _ => "HS256",
+    }
+}
+
+/// RFC 7638 JWK thumbprint, used as the `kid`
+fn thumbprint(jwk: &Jwk) -> String {
+    let field = |value: &Option<String>| value.as_deref().unwrap_or_default().to_string();
+    // required members only, in lexicographic order, no whitespace
+    let canonical = match jwk.kty.as_str() {
+        "RSA" => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            field(&jwk.e),
+            field(&jwk.n)
+        ),
+        "EC" => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            field(&jwk.crv),
+            field(&jwk.x),
+            field(&jwk.y)
+        ),
+        _ => format!(
+            r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#,
+            field(&jwk.crv),
+            field(&jwk.x)
+        ),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
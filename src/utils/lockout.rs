@@ -0,0 +1,270 @@
+use actix_web::HttpResponse;
+use actix_web::http::header::RETRY_AFTER;
+use chrono::Utc;
+use mongodb::Database;
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::ReturnDocument;
+use std::env;
+
+use crate::models::login_attempt::LoginAttempt;
+use crate::utils::audit::record_security_event;
+
+/// Same answer for every locked email, whatever the password and whether it has an account
+pub const ACCOUNT_LOCKED_MESSAGE: &str = "Too many failed login attempts, try again later";
+
+/// When repeated failures lock an email address, and for how long
+pub struct LockoutPolicy {
+    pub threshold: i64,    // consecutive failures per lockout
+    pub base_seconds: i64, // first lockout, doubled by every further one
+    pub max_seconds: i64,  // longest lockout, and how long failures are remembered
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: 5,
+            base_seconds: 60,
+            max_seconds: 60 * 60 * 24,
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// Defaults overridden by `LOCKOUT_THRESHOLD`, `LOCKOUT_BASE_SECONDS` and `LOCKOUT_MAX_SECONDS`
+    pub fn from_env() -> Self {
+        let defaults = LockoutPolicy::default();
+        let var = |name: &str, default: i64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|v: &i64| *v > 0)
+                .unwrap_or(default)
+        };
+        LockoutPolicy {
+            threshold: var("LOCKOUT_THRESHOLD", defaults.threshold),
+            base_seconds: var("LOCKOUT_BASE_SECONDS", defaults.base_seconds),
+            max_seconds: var("LOCKOUT_MAX_SECONDS", defaults.max_seconds),
+        }
+    }
+
+    /// Lock duration after `failures` consecutive failures, None if they don't lock the email.
+    /// Every `threshold` failures lock it again, twice as long as the previous time.
+    fn lock_seconds(&self, failures: i64) -> Option<i64> {
+        if failures < self.threshold || failures % self.threshold != 0 {
+            return None;
+        }
+        let doublings = (failures / self.threshold - 1).min(32) as u32;
+        Some(
+            self.base_seconds
+                .saturating_mul(1_i64 << doublings)
+                .min(self.max_seconds),
+        )
+    }
+}
+
+/// Seconds until a locked email can log in again, None when it isn't locked.
+/// Check it before the password, for accounts and unknown emails alike.
+pub async fn locked_for(db: &Database, email: &str) -> Result<Option<i64>, MongoError> {
+    let attempt = db
+        .collection::<LoginAttempt>("login_attempts")
+        .find_one(doc! { "email": email })
+        .await?;
+    let Some(locked_until) = attempt.and_then(|attempt| attempt.locked_until) else {
+        return Ok(None);
+    };
+    let remaining = locked_until.timestamp_millis() - Utc::now().timestamp_millis();
+    Ok((remaining > 0).then(|| (remaining + 999) / 1000))
+}
+
+/// `429 Too Many Requests` for a locked email, with `Retry-After`
+pub fn locked_response(seconds: i64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, seconds.to_string()))
+        .body(ACCOUNT_LOCKED_MESSAGE)
+}
+
+/// Count a failed password or second factor for an email, with or without an account,
+/// locking it at the policy's threshold
+pub async fn record_login_failure(db: &Database, email: &str) -> Result<(), MongoError> {
+    if let Some((seconds, failures)) = record_failure(db, email).await? {
+        record_security_event(
+            db,
+            "account_locked",
+            Some(email),
+            None,
+            format!(
+                "Locked for {} s after {} consecutive failed logins",
+                seconds, failures
+            ),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Failures of unknown user codes are counted per client address under their own key,
+/// which no email address can collide with
+fn user_code_key(client_ip: &str) -> String {
+    format!("user_code {}", client_ip)
+}
+
+/// Seconds until a client address may enter device user codes again (RFC 8628 section 5.1)
+pub async fn user_code_locked_for(
+    db: &Database,
+    client_ip: &str,
+) -> Result<Option<i64>, MongoError> {
+    locked_for(db, &user_code_key(client_ip)).await
+}
+
+/// Count an unknown or expired device user code entered from a client address,
+/// backing off like failed logins do
+pub async fn record_user_code_failure(db: &Database, client_ip: &str) -> Result<(), MongoError> {
+    if let Some((seconds, failures)) = record_failure(db, &user_code_key(client_ip)).await? {
+        record_security_event(
+            db,
+            "user_code_locked",
+            None,
+            None,
+            format!(
+                "{} locked for {} s after {} unknown user codes",
+                client_ip, seconds, failures
+            ),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Count a failure under `key`, returning the lock duration and failure count when it locks
+async fn record_failure(db: &Database, key: &str) -> Result<Option<(i64, i64)>, MongoError> {
+    let policy = LockoutPolicy::from_env();
+    let now_millis = Utc::now().timestamp_millis();
+    let attempts = db.collection::<LoginAttempt>("login_attempts");
+
+    // one pipeline update, so concurrent failures can't race: a count whose window has passed
+    // starts over here, as the TTL monitor only runs about once a minute. The server retries
+    // an upsert that loses the race on the unique email index itself.
+    let now = DateTime::from_millis(now_millis);
+    let current = doc! { "$gt": ["$expires_at", now] };
+    let Some(attempt) = attempts
+        .find_one_and_update(
+            doc! { "email": key },
+            vec![doc! { "$set": {
+                "failed_logins": { "$cond": [
+                    current.clone(),
+                    { "$add": [{ "$ifNull": ["$failed_logins", 0_i64] }, 1_i64] },
+                    1_i64,
+                ]},
+                "locked_until": { "$cond": [current.clone(), "$locked_until", "$$REMOVE"] },
+                "expires_at": { "$max": [
+                    { "$cond": [current, "$expires_at", now] },
+                    DateTime::from_millis(now_millis + policy.max_seconds * 1000),
+                ]},
+            }}],
+        )
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let Some(seconds) = policy.lock_seconds(attempt.failed_logins) else {
+        return Ok(None);
+    };
+    let locked_until = now_millis + seconds * 1000;
+    attempts
+        .update_one(
+            doc! { "email": key },
+            doc! {
+                "$set": { "locked_until": DateTime::from_millis(locked_until) },
+                // the count outlives the lock, so the next one is longer
+                "$max": { "expires_at": DateTime::from_millis(locked_until + policy.max_seconds * 1000) },
+            },
+        )
+        .await?;
+    Ok(Some((seconds, attempt.failed_logins)))
+}
+
+/// A complete login resets the failure count
+pub async fn record_login_success(db: &Database, email: &str) -> Result<(), MongoError> {
+    unlock_account(db, email).await
+}
+
+/// Clear the failure count and any lock of an email
+pub async fn unlock_account(db: &Database, email: &str) -> Result<(), MongoError> {
+    db.collection::<LoginAttempt>("login_attempts")
+        .delete_one(doc! { "email": email })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::join_all;
+    use mongodb::bson::{DateTime, doc};
+
+    use super::{locked_for, record_login_failure};
+    use crate::config::test_db;
+    use crate::models::login_attempt::LoginAttempt;
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGODB_URI"]
+    async fn concurrent_failures_are_all_counted() {
+        let db = test_db().await;
+
+        let results =
+            join_all((0..10).map(|_| record_login_failure(&db, "alice@example.com"))).await;
+        assert!(results.iter().all(Result::is_ok), "{:?}", results);
+
+        let attempt = db
+            .collection::<LoginAttempt>("login_attempts")
+            .find_one(doc! { "email": "alice@example.com" })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attempt.failed_logins, 10);
+        assert!(
+            locked_for(&db, "alice@example.com")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        db.drop().await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGODB_URI"]
+    async fn an_expired_count_starts_over() {
+        let db = test_db().await;
+        let attempts = db.collection::<LoginAttempt>("login_attempts");
+        // left behind by the TTL monitor, lock and all
+        attempts
+            .insert_one(LoginAttempt {
+                id: None,
+                email: "alice@example.com".to_string(),
+                failed_logins: 4,
+                locked_until: Some(DateTime::from_millis(0)),
+                expires_at: DateTime::from_millis(0),
+            })
+            .await
+            .unwrap();
+
+        record_login_failure(&db, "alice@example.com")
+            .await
+            .unwrap();
+
+        let attempt = attempts
+            .find_one(doc! { "email": "alice@example.com" })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attempt.failed_logins, 1);
+        assert_eq!(attempt.locked_until, None);
+        assert!(attempt.expires_at > DateTime::now());
+
+        db.drop().await.unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use futures_util::future::BoxFuture;
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// A plain-text email
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Outgoing mail. Handlers take `web::Data<dyn Mailer>`; `mailer_from_env` picks the
+/// implementation at startup.
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>>;
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, String> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| format!("Invalid recipient {}: {}", email.to, e))?;
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| format!("Failed to build email: {}", e))
+}
+
+/// Sends through an SMTP relay (STARTTLS or TLS)
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            self.transport
+                .send(message)
+                .await
+                .map_err(|e| format!("SMTP error: {}", e))?;
+            Ok(())
+        })
+    }
+}
+
+/// Writes each message as an `.eml` file, for development
+pub struct FileMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+            let path = self.dir.join(format!("{}.eml", Uuid::new_v4()));
+            std::fs::write(&path, message.formatted())
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            println!("-> utils/mailer.rs - wrote {}", path.display());
+            Ok(())
+        })
+    }
+}
+
+/// Keeps messages in memory, for tests
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    /// Messages sent so far, oldest first
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+        self.sent.lock().unwrap().push(email.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Mailer selected by `MAILER`: `smtp` (`SMTP_HOST`, `SMTP_PORT`, `SMTP_USERNAME`,
+/// `SMTP_PASSWORD`), `file` (default, `.eml` files in `MAIL_DIR`) or `memory`.
+/// The sender is `MAIL_FROM`. Panics on an invalid configuration.
+pub fn mailer_from_env() -> Arc<dyn Mailer> {
+    let from: Mailbox = env::var("MAIL_FROM")
+        .unwrap_or_else(|_| "no-reply@localhost".to_string())
+        .parse()
+        .expect("MAIL_FROM must be an email address");
+
+    match env::var("MAILER").as_deref().unwrap_or("file") {
+        "smtp" => {
+            let host = env::var("SMTP_HOST").expect("SMTP_HOST must be set for MAILER=smtp");
+            let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                .expect("Invalid SMTP_HOST");
+            if let Ok(port) = env::var("SMTP_PORT") {
+                builder = builder.port(port.parse().expect("SMTP_PORT must be a port number"));
+            }
+            if let (Ok(username), Ok(password)) =
+                (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD"))
+            {
+                builder = builder.credentials(Credentials::new(username, password));
+            }
+            Arc::new(SmtpMailer {
+                transport: builder.build(),
+                from,
+            })
+        }
+        "file" => Arc::new(FileMailer {
+            dir: PathBuf::from(env::var("MAIL_DIR").unwrap_or_else(|_| "mail".to_string())),
+            from,
+        }),
+        "memory" => Arc::new(MemoryMailer::default()),
+        other => panic!("Unknown MAILER: {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn memory_mailer_keeps_sent_messages() {
+        let mailer = MemoryMailer::default();
+        let email = Email {
+            to: "user@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "Body".to_string(),
+        };
+        mailer.send(&email).await.unwrap();
+        mailer.send(&email).await.unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].to, "user@example.com");
+        assert_eq!(sent[0].subject, "Hello");
+    }
+}
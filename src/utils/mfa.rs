@@ -0,0 +1,180 @@
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+
+use crate::models::mfa_challenge::MfaChallenge;
+use crate::models::user::User;
+use crate::utils::audit::record_security_event;
+use crate::utils::hash::{hash_password, verify_password};
+use crate::utils::token::{
+    generate_recovery_code, generate_token, hash_token, normalize_recovery_code,
+};
+use crate::utils::totp::verify_code;
+
+pub const MFA_CHALLENGE_MINUTES: i64 = 5;
+/// Wrong codes a challenge survives; the login has to start over after that
+pub const MFA_MAX_ATTEMPTS: i32 = 5;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Store a challenge for a user whose password checked out, returns the challenge token
+pub async fn create_mfa_challenge(
+    db: &Database,
+    user_id: ObjectId,
+    scope: &str,
+    device_name: Option<&str>,
+) -> Result<String, MongoError> {
+    let token = generate_token();
+    db.collection::<MfaChallenge>("mfa_challenges")
+        .insert_one(MfaChallenge {
+            id: None,
+            token_hash: hash_token(&token),
+            user_id,
+            scope: scope.to_string(),
+            device_name: device_name.map(str::to_owned),
+            attempts: 0,
+            expires_at: DateTime::from_millis(
+                (Utc::now() + Duration::minutes(MFA_CHALLENGE_MINUTES)).timestamp_millis(),
+            ),
+        })
+        .await?;
+    Ok(token)
+}
+
+/// Check a second factor of the user: a TOTP code, or one of their recovery codes.
+/// Either is used up by a successful check.
+pub async fn verify_second_factor(
+    db: &Database,
+    user: &User,
+    code: &str,
+) -> Result<bool, MongoError> {
+    if verify_totp(db, user, code).await? {
+        return Ok(true);
+    }
+    if use_recovery_code(db, user, code).await? {
+        record_security_event(
+            db,
+            "recovery_code_used",
+            Some(&user.email),
+            None,
+            format!(
+                "MFA recovery code used, {} left",
+                user.recovery_codes.len() - 1
+            ),
+        )
+        .await;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Check a TOTP code of the user and use it up: a code is accepted once, and so is every
+/// code of an earlier time step
+async fn verify_totp(db: &Database, user: &User, code: &str) -> Result<bool, MongoError> {
+    let Some(secret) = user.totp_secret.as_deref() else {
+        return Ok(false);
+    };
+    let Some(step) = verify_code(secret, code, Utc::now().timestamp()) else {
+        return Ok(false);
+    };
+
+    // conditional update, so two requests can't both use the same code
+    let result = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": user.id, "totp_last_step": { "$lt": step } },
+            doc! { "$set": { "totp_last_step": step } },
+        )
+        .await?;
+    Ok(result.modified_count == 1)
+}
+
+/// Drop the recovery code the code matches, if any
+async fn use_recovery_code(db: &Database, user: &User, code: &str) -> Result<bool, MongoError> {
+    let code = normalize_recovery_code(code);
+    let Some(hash) = user
+        .recovery_codes
+        .iter()
+        .find(|hash| verify_password(hash, &code).unwrap_or(false))
+    else {
+        return Ok(false);
+    };
+
+    // conditional update, so two requests can't both use the same code
+    let result = db
+        .collection::<User>("users")
+        .update_one(
+            doc! { "_id": user.id, "recovery_codes": hash },
+            doc! { "$pull": { "recovery_codes": hash } },
+        )
+        .await?;
+    Ok(result.modified_count == 1)
+}
+
+/// A fresh set of recovery codes: the codes to show the user once, and their hashes to store
+pub fn new_recovery_codes() -> Result<(Vec<String>, Vec<String>), argon2::password_hash::Error> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes = codes
+        .iter()
+        .map(|code| hash_password(&normalize_recovery_code(code)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((codes, hashes))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use mongodb::bson::doc;
+
+    use super::verify_totp;
+    use crate::config::test_db;
+    use crate::models::user::User;
+    use crate::utils::role::default_user_roles;
+    use crate::utils::scope::default_user_scopes;
+    use crate::utils::totp::{TOTP_STEP_SECONDS, code_for_step, generate_secret};
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGODB_URI"]
+    async fn totp_codes_are_accepted_once() {
+        let db = test_db().await;
+        let secret = generate_secret();
+        let users = db.collection::<User>("users");
+        let id = users
+            .insert_one(User {
+                id: None,
+                username: "alice".to_string(),
+                email: "alice@example.com".to_string(),
+                password: String::new(),
+                email_verified: true,
+                scopes: default_user_scopes(),
+                roles: default_user_roles(),
+                token_version: 0,
+                totp_secret: Some(secret.clone()),
+                totp_pending_secret: None,
+                totp_last_step: 0,
+                recovery_codes: Vec::new(),
+                webauthn_credentials: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .inserted_id;
+        let load = || async { users.find_one(doc! { "_id": &id }).await.unwrap().unwrap() };
+
+        let step = Utc::now().timestamp() / TOTP_STEP_SECONDS;
+        let previous = code_for_step(&secret, step - 1).unwrap();
+        let current = code_for_step(&secret, step).unwrap();
+
+        let user = load().await;
+        assert!(verify_totp(&db, &user, &current).await.unwrap());
+        // the same code again: the copy still says totp_last_step 0, the database doesn't
+        assert!(!verify_totp(&db, &user, &current).await.unwrap());
+        // an earlier code still within the skew
+        assert!(!verify_totp(&db, &load().await, &previous).await.unwrap());
+        assert_eq!(load().await.totp_last_step, step);
+
+        db.drop().await.unwrap();
+    }
+}
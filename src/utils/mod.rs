@@ -1,2 +1,18 @@
+pub mod audit;
+pub mod email_verification;
 pub mod hash;
 pub mod jwt;
+pub mod keys;
+pub mod lockout;
+pub mod mailer;
+pub mod mfa;
+pub mod oauth;
+pub mod refresh_token;
+pub mod revocation;
+pub mod role;
+pub mod scope;
+pub mod session;
+pub mod token;
+pub mod token_version;
+pub mod totp;
+pub mod webauthn;
@@ -0,0 +1,178 @@
+use chrono::{Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+
+use crate::models::refresh_token::RefreshToken;
+use crate::models::session::Session;
+use crate::models::user::User;
+use crate::utils::audit::record_security_event;
+use crate::utils::jwt::{ACCESS_TOKEN_MINUTES, Claims};
+use crate::utils::revocation::revoke_session_tokens;
+
+/// Why a refresh token can't be rotated
+#[derive(Debug)]
+pub enum RefreshTokenError {
+    /// unknown, expired or revoked token
+    Invalid,
+    /// the token was already rotated: its family has been revoked
+    Reused,
+    Database(MongoError),
+}
+
+impl From<MongoError> for RefreshTokenError {
+    fn from(e: MongoError) -> Self {
+        RefreshTokenError::Database(e)
+    }
+}
+
+/// Record a newly issued refresh token as the active member of its family
+pub async fn store_refresh_token(
+    db: &Database,
+    claims: &Claims,
+    family_id: &str,
+    user_id: ObjectId,
+) -> Result<(), MongoError> {
+    db.collection::<RefreshToken>("refresh_tokens")
+        .insert_one(RefreshToken {
+            id: None,
+            jti: claims.jti.clone().unwrap_or_default(),
+            family_id: family_id.to_string(),
+            user_id,
+            client_id: claims.client_id.clone(),
+            status: "active".to_string(),
+            created_at: DateTime::now(),
+            expires_at: DateTime::from_millis(claims.exp as i64 * 1000),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Mark a verified refresh token as used and return its record so a child can be issued.
+/// Presenting a token that was already rotated revokes its whole family.
+pub async fn rotate_refresh_token(
+    db: &Database,
+    claims: &Claims,
+) -> Result<RefreshToken, RefreshTokenError> {
+    // tokens issued before refresh token families carry no jti
+    let jti = claims.jti.as_deref().ok_or(RefreshTokenError::Invalid)?;
+    let collection = db.collection::<RefreshToken>("refresh_tokens");
+
+    if let Some(token) = collection
+        .find_one_and_update(
+            doc! { "jti": jti, "status": "active" },
+            doc! { "$set": { "status": "rotated" }},
+        )
+        .await?
+    {
+        return Ok(token);
+    }
+
+    match collection.find_one(doc! { "jti": jti }).await? {
+        Some(token) if token.status == "rotated" => {
+            revoke_refresh_family(db, &token.family_id).await?;
+            let user = db
+                .collection::<User>("users")
+                .find_one(doc! { "_id": token.user_id })
+                .await?;
+            record_security_event(
+                db,
+                "refresh_token_reuse",
+                user.as_ref().map(|user| user.email.as_str()),
+                token.client_id.as_deref(),
+                format!(
+                    "Rotated refresh token {} was presented again, family {} revoked",
+                    token.jti, token.family_id
+                ),
+            )
+            .await;
+            Err(RefreshTokenError::Reused)
+        }
+        _ => Err(RefreshTokenError::Invalid),
+    }
+}
+
+/// Revoke a refresh token family and end the session it belongs to
+pub async fn revoke_refresh_family(db: &Database, family_id: &str) -> Result<(), MongoError> {
+    db.collection::<RefreshToken>("refresh_tokens")
+        .update_many(
+            doc! { "family_id": family_id },
+            doc! { "$set": { "status": "revoked" }},
+        )
+        .await?;
+    let session = db
+        .collection::<Session>("sessions")
+        .find_one_and_delete(doc! { "session_id": family_id })
+        .await?;
+    if let Some(session) = session {
+        revoke_access_tokens(db, &session).await?;
+    }
+
+    Ok(())
+}
+
+/// Revoke the family a refresh token belongs to (RFC 7009 revocation of a refresh token)
+pub async fn revoke_refresh_token(db: &Database, claims: &Claims) -> Result<(), MongoError> {
+    let Some(jti) = claims.jti.as_deref() else {
+        return Ok(());
+    };
+    let token = db
+        .collection::<RefreshToken>("refresh_tokens")
+        .find_one(doc! { "jti": jti })
+        .await?;
+
+    match token {
+        Some(token) => revoke_refresh_family(db, &token.family_id).await,
+        None => Ok(()),
+    }
+}
+
+/// Revoke every refresh token of a user and end all of their sessions
+pub async fn revoke_user_refresh_tokens(
+    db: &Database,
+    user_id: ObjectId,
+) -> Result<(), MongoError> {
+    db.collection::<RefreshToken>("refresh_tokens")
+        .update_many(
+            doc! { "user_id": user_id, "status": "active" },
+            doc! { "$set": { "status": "revoked" }},
+        )
+        .await?;
+    let sessions: Vec<Session> = db
+        .collection::<Session>("sessions")
+        .find(doc! { "user_id": user_id })
+        .await?
+        .try_collect()
+        .await?;
+    for session in &sessions {
+        revoke_access_tokens(db, session).await?;
+    }
+    db.collection::<Session>("sessions")
+        .delete_many(doc! { "user_id": user_id })
+        .await?;
+
+    Ok(())
+}
+
+/// Access tokens of an ended session stop working right away, not when they expire
+async fn revoke_access_tokens(db: &Database, session: &Session) -> Result<(), MongoError> {
+    // no token of the session outlives its last refresh token or a fresh access token
+    let expires_at = (session.expires_at.timestamp_millis() / 1000)
+        .max((Utc::now() + Duration::minutes(ACCESS_TOKEN_MINUTES)).timestamp());
+    revoke_session_tokens(db, &session.session_id, expires_at).await
+}
+
+pub async fn is_refresh_token_active(db: &Database, claims: &Claims) -> Result<bool, MongoError> {
+    let Some(jti) = claims.jti.as_deref() else {
+        return Ok(false);
+    };
+    let token = db
+        .collection::<RefreshToken>("refresh_tokens")
+        .find_one(doc! { "jti": jti, "status": "active" })
+        .await?;
+
+    Ok(token.is_some())
+}
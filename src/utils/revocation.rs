@@ -0,0 +1,151 @@
+use chrono::Utc;
+use mongodb::Database;
+use mongodb::bson::{DateTime, Document, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::UpdateOptions;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::models::revoked_token::RevokedToken;
+use crate::utils::jwt::{Claims, verify_jwt};
+
+/// How long a "not revoked" answer from Mongo is trusted. Revocations made by this
+/// process are cached right away; other instances see them after at most this long.
+pub const REVOCATION_CACHE_SECONDS: u64 = 10;
+
+/// In-memory view of the revocation store, keyed by "jti:<id>" or "sid:<id>"
+#[derive(Default)]
+struct RevocationCache {
+    revoked: HashMap<String, i64>, // key -> expiry (seconds since epoch)
+    not_revoked: HashMap<String, Instant>, // key -> when Mongo was last asked
+}
+
+impl RevocationCache {
+    fn is_revoked(&mut self, keys: &[String]) -> Option<bool> {
+        let now = Utc::now().timestamp();
+        self.revoked.retain(|_, expires_at| *expires_at > now);
+        self.not_revoked
+            .retain(|_, checked| checked.elapsed() < Duration::from_secs(REVOCATION_CACHE_SECONDS));
+
+        if keys.iter().any(|key| self.revoked.contains_key(key)) {
+            Some(true)
+        } else if keys.iter().all(|key| self.not_revoked.contains_key(key)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+fn cache() -> &'static Mutex<RevocationCache> {
+    static CACHE: OnceLock<Mutex<RevocationCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(RevocationCache::default()))
+}
+
+fn cache_keys(claims: &Claims) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(jti) = claims.jti.as_deref() {
+        keys.push(format!("jti:{}", jti));
+    }
+    if let Some(sid) = claims.sid.as_deref() {
+        keys.push(format!("sid:{}", sid));
+    }
+    keys
+}
+
+async fn store_revocation(
+    db: &Database,
+    field: &str,
+    id: &str,
+    expires_at: i64,
+) -> Result<(), MongoError> {
+    db.collection::<RevokedToken>("revoked_tokens")
+        .update_one(
+            doc! { field: id },
+            doc! {
+                "$setOnInsert": { field: id },
+                "$max": { "expires_at": DateTime::from_millis(expires_at * 1000) },
+            },
+        )
+        .with_options(UpdateOptions::builder().upsert(true).build())
+        .await?;
+
+    let key = format!("{}:{}", field, id);
+    let mut cache = cache().lock().unwrap();
+    cache.not_revoked.remove(&key);
+    cache.revoked.insert(key, expires_at);
+
+    Ok(())
+}
+
+/// Put a token on the revocation list until it expires.
+/// Tokens that no longer verify are already unusable and are ignored.
+pub async fn revoke_token(db: &Database, token: &str) -> Result<(), MongoError> {
+    let Ok(claims) = verify_jwt(token) else {
+        return Ok(());
+    };
+    revoke_claims(db, &claims).await
+}
+
+/// Revoke a single verified token by its `jti`
+pub async fn revoke_claims(db: &Database, claims: &Claims) -> Result<(), MongoError> {
+    match claims.jti.as_deref() {
+        Some(jti) => store_revocation(db, "jti", jti, claims.exp as i64).await,
+        // tokens issued before jti existed expire on their own shortly
+        None => Ok(()),
+    }
+}
+
+/// Revoke every token carrying this session id (`sid`) until `expires_at`
+pub async fn revoke_session_tokens(
+    db: &Database,
+    session_id: &str,
+    expires_at: i64,
+) -> Result<(), MongoError> {
+    store_revocation(db, "sid", session_id, expires_at).await
+}
+
+/// Whether the token itself or its session has been revoked
+pub async fn is_revoked(db: &Database, claims: &Claims) -> Result<bool, MongoError> {
+    let keys = cache_keys(claims);
+    if keys.is_empty() {
+        return Ok(false);
+    }
+    if let Some(revoked) = cache().lock().unwrap().is_revoked(&keys) {
+        return Ok(revoked);
+    }
+
+    let mut filters: Vec<Document> = Vec::new();
+    if let Some(jti) = claims.jti.as_deref() {
+        filters.push(doc! { "jti": jti });
+    }
+    if let Some(sid) = claims.sid.as_deref() {
+        filters.push(doc! { "sid": sid });
+    }
+    let revoked = db
+        .collection::<RevokedToken>("revoked_tokens")
+        .find_one(doc! { "$or": filters })
+        .await?;
+
+    let mut cache = cache().lock().unwrap();
+    match revoked {
+        Some(revoked) => {
+            let key = match (revoked.jti, revoked.sid) {
+                (Some(jti), _) => format!("jti:{}", jti),
+                (None, Some(sid)) => format!("sid:{}", sid),
+                (None, None) => return Ok(true),
+            };
+            cache
+                .revoked
+                .insert(key, revoked.expires_at.timestamp_millis() / 1000);
+            Ok(true)
+        }
+        None => {
+            for key in keys {
+                cache.not_revoked.insert(key, Instant::now());
+            }
+            Ok(false)
+        }
+    }
+}
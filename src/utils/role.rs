@@ -0,0 +1,13 @@
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
+
+pub const SUPPORTED_ROLES: [&str; 2] = [ROLE_USER, ROLE_ADMIN];
+
+/// Roles of a newly registered user
+pub fn default_user_roles() -> Vec<String> {
+    vec![ROLE_USER.to_string()]
+}
+
+pub fn has_any_role(roles: &[String], wanted: &[String]) -> bool {
+    roles.iter().any(|role| wanted.contains(role))
+}
@@ -0,0 +1,68 @@
+use crate::utils::oauth::OAuthError;
+
+/// Every scope this server knows: OpenID Connect scopes plus the `/api` scopes
+pub const SUPPORTED_SCOPES: [&str; 5] =
+    ["openid", "profile", "email", "offline_access", "sessions"];
+
+/// Scopes a user may grant unless their record says otherwise
+pub fn default_user_scopes() -> Vec<String> {
+    SUPPORTED_SCOPES.iter().map(|s| s.to_string()).collect()
+}
+
+pub fn has_scope(scope: Option<&str>, wanted: &str) -> bool {
+    scope.is_some_and(|scope| scope.split(' ').any(|s| s == wanted))
+}
+
+fn split(scope: &str) -> impl Iterator<Item = &str> {
+    scope.split(' ').filter(|s| !s.is_empty())
+}
+
+/// Validate a requested scope against what the client is registered for
+/// (`None` allows every supported scope). An omitted request defaults to all of them.
+pub fn resolve_client_scope(
+    requested: Option<&str>,
+    client_scope: Option<&str>,
+) -> Result<String, OAuthError> {
+    let allowed: Vec<&str> = match client_scope {
+        Some(client_scope) => split(client_scope).collect(),
+        None => SUPPORTED_SCOPES.to_vec(),
+    };
+
+    let Some(requested) = requested.filter(|r| !r.trim().is_empty()) else {
+        return Ok(allowed.join(" "));
+    };
+
+    let mut granted: Vec<&str> = Vec::new();
+    for scope in split(requested) {
+        if !SUPPORTED_SCOPES.contains(&scope) {
+            return Err(OAuthError::invalid_scope(format!(
+                "Unknown scope: {}",
+                scope
+            )));
+        }
+        if !allowed.contains(&scope) {
+            return Err(OAuthError::invalid_scope(format!(
+                "Client is not allowed to request scope: {}",
+                scope
+            )));
+        }
+        if !granted.contains(&scope) {
+            granted.push(scope);
+        }
+    }
+    Ok(granted.join(" "))
+}
+
+/// Narrow a scope to what the user may grant (RFC 6749 section 3.3 allows issuing less
+/// than requested; the token response reports the granted scope)
+pub fn restrict_to_user(scope: &str, user_scopes: &[String]) -> Result<String, OAuthError> {
+    let granted: Vec<&str> = split(scope)
+        .filter(|s| user_scopes.iter().any(|u| u == s))
+        .collect();
+    if granted.is_empty() {
+        return Err(OAuthError::invalid_scope(
+            "None of the requested scopes are allowed for this user",
+        ));
+    }
+    Ok(granted.join(" "))
+}
@@ -0,0 +1,65 @@
+use actix_web::HttpRequest;
+use actix_web::http::header::USER_AGENT;
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+use uuid::Uuid;
+
+use crate::models::session::Session;
+use crate::utils::jwt::Claims;
+
+/// Open a new session for a login and return its id (also the refresh token family id)
+pub async fn start_session(
+    db: &Database,
+    req: &HttpRequest,
+    user_id: ObjectId,
+    client_id: Option<&str>,
+    device_name: Option<&str>,
+) -> Result<String, MongoError> {
+    let session_id = Uuid::new_v4().to_string();
+    let now = DateTime::now();
+
+    db.collection::<Session>("sessions")
+        .insert_one(Session {
+            id: None,
+            session_id: session_id.clone(),
+            user_id,
+            client_id: client_id.map(str::to_owned),
+            device_name: device_name.map(str::to_owned),
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_owned),
+            ip_address: req.peer_addr().map(|addr| addr.ip().to_string()),
+            refresh_token_jti: None,
+            created_at: now,
+            // replaced by the first refresh token's expiry
+            expires_at: now,
+            last_used_at: now,
+        })
+        .await?;
+
+    Ok(session_id)
+}
+
+/// Point the session at its newest refresh token and extend it to that token's expiry
+pub async fn touch_session(
+    db: &Database,
+    session_id: &str,
+    refresh_claims: &Claims,
+) -> Result<(), MongoError> {
+    db.collection::<Session>("sessions")
+        .update_one(
+            doc! { "session_id": session_id },
+            doc! { "$set": {
+                "refresh_token_jti": &refresh_claims.jti,
+                "last_used_at": DateTime::now(),
+                "expires_at": DateTime::from_millis(refresh_claims.exp as i64 * 1000),
+            }},
+        )
+        .await?;
+
+    Ok(())
+}
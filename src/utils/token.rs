@@ -0,0 +1,52 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+/// Generate an opaque, URL-safe random token (256 bits of entropy)
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// SHA-256 digest of a token, used to look tokens up without storing them in clear
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// RFC 8628 user code: 8 consonants as "XXXX-XXXX", easy to read and type on another device
+pub fn generate_user_code() -> String {
+    const CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+    let code: String = (0..8)
+        .map(|_| CHARSET[(OsRng.next_u32() % CHARSET.len() as u32) as usize] as char)
+        .collect();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+/// Canonical form of a typed user code: upper case, dashes and spaces removed
+pub fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// MFA recovery code: 10 characters as "xxxxx-xxxxx", without look-alikes such as 0/o or 1/l
+pub fn generate_recovery_code() -> String {
+    const CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let code: String = (0..10)
+        .map(|_| CHARSET[(OsRng.next_u32() % CHARSET.len() as u32) as usize] as char)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Canonical form of a typed recovery code: lower case, dashes and spaces removed
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,81 @@
+use mongodb::Database;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use mongodb::options::ReturnDocument;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::models::user::{User, subject_filter};
+use crate::utils::jwt::Claims;
+use crate::utils::revocation::REVOCATION_CACHE_SECONDS;
+
+/// Current token_version per user subject, and when Mongo was last asked.
+/// Versions bumped by this process are cached right away, like revocations.
+fn cache() -> &'static Mutex<HashMap<String, (i64, Instant)>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, (i64, Instant)>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Whether the token predates the user's last password change, or the user is gone
+pub async fn is_outdated(db: &Database, claims: &Claims) -> Result<bool, MongoError> {
+    // client tokens have no user
+    if claims.is_client() {
+        return Ok(false);
+    }
+
+    let cached = {
+        let mut cache = cache().lock().unwrap();
+        cache.retain(|_, (_, checked)| {
+            checked.elapsed() < Duration::from_secs(REVOCATION_CACHE_SECONDS)
+        });
+        cache.get(&claims.sub).map(|(version, _)| *version)
+    };
+
+    let version = match cached {
+        Some(version) => version,
+        None => {
+            let Some(user) = db
+                .collection::<User>("users")
+                .find_one(subject_filter(&claims.sub))
+                .await?
+            else {
+                return Ok(true);
+            };
+            cache()
+                .lock()
+                .unwrap()
+                .insert(claims.sub.clone(), (user.token_version, Instant::now()));
+            user.token_version
+        }
+    };
+
+    Ok(!is_current_version(claims, version))
+}
+
+/// Whether the token was issued at the user's current token_version; tokens from before
+/// versions existed count as version 0
+pub fn is_current_version(claims: &Claims, token_version: i64) -> bool {
+    claims.ver.unwrap_or(0) == token_version
+}
+
+/// Sign out every access and refresh token issued to the user so far
+pub async fn bump_token_version(db: &Database, user_id: ObjectId) -> Result<(), MongoError> {
+    let user = db
+        .collection::<User>("users")
+        .find_one_and_update(
+            doc! { "_id": user_id },
+            doc! { "$inc": { "token_version": 1_i64 } },
+        )
+        .return_document(ReturnDocument::After)
+        .await?;
+
+    if let Some(user) = user {
+        cache()
+            .lock()
+            .unwrap()
+            .insert(user.subject(), (user.token_version, Instant::now()));
+    }
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use ring::hmac;
+
+// RFC 6238 with the parameters every authenticator app supports: HMAC-SHA1, 6 digits, 30 s steps
+pub const TOTP_STEP_SECONDS: i64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Steps of clock drift accepted on either side of the current one
+pub const TOTP_SKEW_STEPS: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generate a 160-bit shared secret, base32-encoded as authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+/// `otpauth://` URI to show as a QR code, see the Key Uri Format of Google Authenticator
+pub fn provisioning_uri(secret: &str, account: &str, issuer: &str) -> String {
+    let label = format!("{}:{}", issuer, account);
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        utf8_percent_encode(&label, NON_ALPHANUMERIC),
+        secret,
+        utf8_percent_encode(issuer, NON_ALPHANUMERIC),
+        TOTP_DIGITS,
+        TOTP_STEP_SECONDS
+    )
+}
+
+/// The time step a valid code belongs to, None if the code doesn't match any step within the skew
+pub fn verify_code(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = now / TOTP_STEP_SECONDS;
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .filter(|step| *step >= 0)
+        .find(|step| format_code(hotp(&key, *step as u64)) == code)
+}
+
+/// The code of a time step, as an authenticator app would show it
+#[cfg(test)]
+pub fn code_for_step(secret: &str, step: i64) -> Option<String> {
+    Some(format_code(hotp(&base32_decode(secret)?, step as u64)))
+}
+
+/// RFC 4226 HOTP value for a counter
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let digest = hmac::sign(&key, &counter.to_be_bytes());
+    let digest = digest.as_ref();
+
+    // dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+fn format_code(value: u32) -> String {
+    format!("{:0width$}", value, width = TOTP_DIGITS as usize)
+}
+
+/// RFC 4648 base32 without padding
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Lenient base32 decoding: ignores case, spaces and padding
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The RFC 6238 appendix B SHA-1 seed, ASCII "12345678901234567890"
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    /// Appendix B times with the last 6 digits of their 8-digit SHA-1 codes
+    const RFC_VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    fn code_at(step: i64) -> String {
+        code_for_step(RFC_SECRET, step).unwrap()
+    }
+
+    #[test]
+    fn rfc_6238_secret_decodes_to_the_ascii_seed() {
+        assert_eq!(
+            base32_decode(RFC_SECRET).unwrap(),
+            b"12345678901234567890".to_vec()
+        );
+    }
+
+    #[test]
+    fn rfc_6238_sha1_vectors() {
+        for (time, code) in RFC_VECTORS {
+            assert_eq!(code_at(time / TOTP_STEP_SECONDS), code, "time {}", time);
+            assert_eq!(
+                verify_code(RFC_SECRET, code, time),
+                Some(time / TOTP_STEP_SECONDS),
+                "time {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_codes_one_step_either_side() {
+        let step = 1234567890 / TOTP_STEP_SECONDS;
+        let code = code_at(step);
+
+        // first and last second of the window
+        let first = (step - TOTP_SKEW_STEPS) * TOTP_STEP_SECONDS;
+        let last = (step + TOTP_SKEW_STEPS + 1) * TOTP_STEP_SECONDS - 1;
+        assert_eq!(verify_code(RFC_SECRET, &code, first), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, last), Some(step));
+
+        // one second outside
+        assert_eq!(verify_code(RFC_SECRET, &code, first - 1), None);
+        assert_eq!(verify_code(RFC_SECRET, &code, last + 1), None);
+    }
+
+    #[test]
+    fn finds_the_step_of_a_drifted_code() {
+        let now = 1234567890;
+        let current = now / TOTP_STEP_SECONDS;
+        for step in [current - 1, current, current + 1] {
+            assert_eq!(verify_code(RFC_SECRET, &code_at(step), now), Some(step));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let now = 1234567890;
+        let code = code_at(now / TOTP_STEP_SECONDS);
+        assert_eq!(
+            verify_code(RFC_SECRET, &format!(" {} ", code), now),
+            Some(now / TOTP_STEP_SECONDS)
+        );
+        assert_eq!(verify_code(RFC_SECRET, &code[1..], now), None);
+        assert_eq!(verify_code(RFC_SECRET, &format!("{}0", code), now), None);
+        assert_eq!(verify_code(RFC_SECRET, "12345a", now), None);
+        assert_eq!(verify_code(RFC_SECRET, "", now), None);
+        assert_eq!(verify_code("not base32!", &code, now), None);
+    }
+
+    #[test]
+    fn no_step_before_the_epoch() {
+        // step -1 doesn't exist, step 0 is the first
+        assert_eq!(verify_code(RFC_SECRET, &code_at(0), 0), Some(0));
+        assert_eq!(verify_code(RFC_SECRET, &code_at(1), 0), Some(1));
+    }
+
+    #[test]
+    fn base32_rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(base32_encode(plain.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn base32_roundtrips_every_length() {
+        for len in 0..=11_usize {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 0xa5) as u8).collect();
+            let encoded = base32_encode(&bytes);
+            assert_eq!(encoded.len(), (len * 8).div_ceil(5), "length {}", len);
+            assert_eq!(base32_decode(&encoded).unwrap(), bytes, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn base32_decoding_is_lenient() {
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW1"), None);
+    }
+
+    #[test]
+    fn generated_secrets_are_160_bits() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+    }
+}
@@ -0,0 +1,822 @@
+use base64::Engine;
+use base64::alphabet::URL_SAFE;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use ciborium::Value;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::{webauthn_origin, webauthn_rp_id};
+
+/// base64url as WebAuthn's JSON encoding uses it; padding is tolerated when decoding
+pub const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// COSE algorithms (RFC 9053) accepted for credentials, in order of preference
+pub const COSE_ES256: i64 = -7;
+pub const COSE_EDDSA: i64 = -8;
+pub const COSE_RS256: i64 = -257;
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [COSE_ES256, COSE_EDDSA, COSE_RS256];
+
+// authenticator data flags (WebAuthn section 6.1)
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// The relying party every ceremony is checked against
+pub struct RelyingParty {
+    pub id: String,     // e.g. "example.com"
+    pub origin: String, // e.g. "https://example.com"
+}
+
+impl RelyingParty {
+    pub fn from_env() -> Self {
+        RelyingParty {
+            id: webauthn_rp_id(),
+            origin: webauthn_origin(),
+        }
+    }
+}
+
+/// Credential created by a registration ceremony
+pub struct NewCredential {
+    pub credential_id: String, // base64url
+    pub public_key: String,    // base64url COSE_Key
+    pub algorithm: i64,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// The challenge a `clientDataJSON` answers, to find the pending ceremony
+pub fn client_challenge(client_data_json: &str) -> Result<String, String> {
+    let bytes = decode(client_data_json, "clientDataJSON")?;
+    let client_data: CollectedClientData =
+        serde_json::from_slice(&bytes).map_err(|_| "Malformed clientDataJSON".to_string())?;
+    Ok(client_data.challenge)
+}
+
+/// Check a registration (`navigator.credentials.create`) answer, WebAuthn section 7.1.
+/// Accepts the `none` attestation format, which browsers send when no attestation is
+/// requested, and `packed` self attestation.
+pub fn verify_registration(
+    client_data_json: &str,
+    attestation_object: &str,
+    challenge: &str,
+    rp: &RelyingParty,
+) -> Result<NewCredential, String> {
+    let client_data = check_client_data(client_data_json, "webauthn.create", challenge, rp)?;
+
+    let attestation_bytes = decode(attestation_object, "attestationObject")?;
+    let attestation: Value = ciborium::from_reader(attestation_bytes.as_slice())
+        .map_err(|_| "Malformed attestationObject".to_string())?;
+    let fmt = map_value(&attestation, "fmt")
+        .and_then(Value::as_text)
+        .ok_or("Missing attestation format")?;
+    let auth_data_bytes = map_value(&attestation, "authData")
+        .and_then(Value::as_bytes)
+        .ok_or("Missing authenticator data")?;
+    let statement = map_value(&attestation, "attStmt").ok_or("Missing attestation statement")?;
+
+    let auth_data = parse_authenticator_data(auth_data_bytes)?;
+    check_authenticator_data(&auth_data, rp, false)?;
+    let (credential_id, public_key) = auth_data
+        .attested_credential
+        .ok_or("No credential in the authenticator data")?;
+    let key = CoseKey::parse(&public_key)?;
+
+    match fmt {
+        "none" => {}
+        "packed" => {
+            // self attestation: signed by the new credential itself
+            if map_value(statement, "x5c").is_some() {
+                return Err("Only self attestation is supported for the packed format".into());
+            }
+            let algorithm = map_value(statement, "alg")
+                .and_then(Value::as_integer)
+                .and_then(|alg| i64::try_from(alg).ok());
+            if algorithm != Some(key.algorithm()) {
+                return Err("Attestation algorithm doesn't match the credential".into());
+            }
+            let signature = map_value(statement, "sig")
+                .and_then(Value::as_bytes)
+                .ok_or("Missing attestation signature")?;
+            key.verify(&signed_data(auth_data_bytes, &client_data), signature)?;
+        }
+        other => return Err(format!("Unsupported attestation format: {}", other)),
+    }
+
+    Ok(NewCredential {
+        credential_id: BASE64URL.encode(credential_id),
+        public_key: BASE64URL.encode(&public_key),
+        algorithm: key.algorithm(),
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// Check an authentication (`navigator.credentials.get`) answer against a stored
+/// credential, WebAuthn section 7.2. Returns the authenticator's signature counter.
+pub fn verify_assertion(
+    client_data_json: &str,
+    authenticator_data: &str,
+    signature: &str,
+    challenge: &str,
+    rp: &RelyingParty,
+    public_key: &str,
+    require_user_verification: bool,
+) -> Result<u32, String> {
+    let client_data = check_client_data(client_data_json, "webauthn.get", challenge, rp)?;
+
+    let auth_data_bytes = decode(authenticator_data, "authenticatorData")?;
+    let auth_data = parse_authenticator_data(&auth_data_bytes)?;
+    check_authenticator_data(&auth_data, rp, require_user_verification)?;
+
+    let key = CoseKey::parse(&decode(public_key, "public key")?)?;
+    key.verify(
+        &signed_data(&auth_data_bytes, &client_data),
+        &decode(signature, "signature")?,
+    )?;
+
+    Ok(auth_data.sign_count)
+}
+
+/// Whether a signature counter moved on from the stored one. Counters only grow, unless the
+/// authenticator doesn't keep one (always 0); anything else hints at a cloned authenticator.
+pub fn sign_count_advances(stored: i64, sign_count: i64) -> bool {
+    (sign_count == 0 && stored == 0) || sign_count > stored
+}
+
+/// A credential id as stored: base64url without padding, however the browser sent it
+pub fn normalize_credential_id(id: &str) -> &str {
+    id.trim_end_matches('=')
+}
+
+fn decode(value: &str, what: &str) -> Result<Vec<u8>, String> {
+    BASE64URL
+        .decode(value)
+        .map_err(|_| format!("Malformed {}", what))
+}
+
+/// Check type, challenge and origin of the client data, returns its raw bytes
+fn check_client_data(
+    client_data_json: &str,
+    expected_type: &str,
+    challenge: &str,
+    rp: &RelyingParty,
+) -> Result<Vec<u8>, String> {
+    let bytes = decode(client_data_json, "clientDataJSON")?;
+    let client_data: CollectedClientData =
+        serde_json::from_slice(&bytes).map_err(|_| "Malformed clientDataJSON".to_string())?;
+
+    if client_data.kind != expected_type {
+        return Err(format!("Expected a {} ceremony", expected_type));
+    }
+    if client_data.challenge != challenge {
+        return Err("Challenge mismatch".into());
+    }
+    if client_data.origin != rp.origin {
+        return Err(format!("Unexpected origin: {}", client_data.origin));
+    }
+    Ok(bytes)
+}
+
+/// What the authenticator signs: authenticator data followed by the client data hash
+fn signed_data(auth_data: &[u8], client_data: &[u8]) -> Vec<u8> {
+    let mut data = auth_data.to_vec();
+    data.extend_from_slice(&Sha256::digest(client_data));
+    data
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>, // credential id, COSE_Key bytes
+}
+
+/// Parse authenticator data (WebAuthn section 6.1)
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, String> {
+    if data.len() < 37 {
+        return Err("Authenticator data too short".into());
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // 16 bytes AAGUID, 2 bytes id length, id, then the CBOR public key
+        let rest = &data[37..];
+        if rest.len() < 18 {
+            return Err("Attested credential data too short".into());
+        }
+        let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        let credential_id = rest
+            .get(18..18 + id_len)
+            .ok_or("Attested credential data too short")?;
+        let mut key_bytes = &rest[18 + id_len..];
+        let available = key_bytes.len();
+        let _: Value = ciborium::from_reader(&mut key_bytes)
+            .map_err(|_| "Malformed credential public key".to_string())?;
+        let key_len = available - key_bytes.len();
+        Some((
+            credential_id.to_vec(),
+            rest[18 + id_len..18 + id_len + key_len].to_vec(),
+        ))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+fn check_authenticator_data(
+    auth_data: &AuthenticatorData,
+    rp: &RelyingParty,
+    require_user_verification: bool,
+) -> Result<(), String> {
+    if auth_data.rp_id_hash != Sha256::digest(rp.id.as_bytes()).as_slice() {
+        return Err("Credential belongs to another relying party".into());
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err("User presence is required".into());
+    }
+    if require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err("User verification is required".into());
+    }
+    Ok(())
+}
+
+fn map_value<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value
+        .as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+/// Credential public key in COSE_Key form (RFC 9052 section 7)
+enum CoseKey {
+    Es256(Vec<u8>), // uncompressed P-256 point
+    EdDsa(Vec<u8>), // Ed25519 public key
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let value: Value =
+            ciborium::from_reader(bytes).map_err(|_| "Malformed credential public key")?;
+        let map = value.as_map().ok_or("Malformed credential public key")?;
+        let param = |label: i64| {
+            map.iter()
+                .find(|(k, _)| k.as_integer() == Some(label.into()))
+                .map(|(_, v)| v)
+        };
+        let int = |label: i64| {
+            param(label)
+                .and_then(Value::as_integer)
+                .and_then(|v| i64::try_from(v).ok())
+        };
+        let bytes = |label: i64| param(label).and_then(Value::as_bytes).cloned();
+
+        // kty (1), alg (3), then key type parameters
+        match (int(1), int(3)) {
+            (Some(2), Some(COSE_ES256)) if int(-1) == Some(1) => {
+                let (Some(x), Some(y)) = (bytes(-2), bytes(-3)) else {
+                    return Err("Malformed EC2 key".into());
+                };
+                if x.len() != 32 || y.len() != 32 {
+                    return Err("Malformed EC2 key".into());
+                }
+                let mut point = vec![0x04];
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                Ok(CoseKey::Es256(point))
+            }
+            (Some(1), Some(COSE_EDDSA)) if int(-1) == Some(6) => bytes(-2)
+                .filter(|x| x.len() == 32)
+                .map(CoseKey::EdDsa)
+                .ok_or_else(|| "Malformed OKP key".into()),
+            (Some(3), Some(COSE_RS256)) => match (bytes(-1), bytes(-2)) {
+                (Some(n), Some(e)) => Ok(CoseKey::Rs256 { n, e }),
+                _ => Err("Malformed RSA key".into()),
+            },
+            _ => Err("Unsupported credential algorithm".into()),
+        }
+    }
+
+    fn algorithm(&self) -> i64 {
+        match self {
+            CoseKey::Es256(_) => COSE_ES256,
+            CoseKey::EdDsa(_) => COSE_EDDSA,
+            CoseKey::Rs256 { .. } => COSE_RS256,
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        let result = match self {
+            // WebAuthn ECDSA signatures are DER-encoded
+            CoseKey::Es256(point) => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                    .verify(message, signature)
+            }
+            CoseKey::EdDsa(key) => {
+                UnparsedPublicKey::new(&signature::ED25519, key).verify(message, signature)
+            }
+            CoseKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                signature,
+            ),
+        };
+        result.map_err(|_| "Invalid signature".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{
+        ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RSA_PKCS1_SHA256,
+        RsaKeyPair,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const CHALLENGE: &str = "c2VydmVyLWNoYWxsZW5nZQ";
+
+    fn rp() -> RelyingParty {
+        RelyingParty {
+            id: "example.com".to_string(),
+            origin: "https://example.com".to_string(),
+        }
+    }
+
+    enum TestKey {
+        Es256(EcdsaKeyPair),
+        EdDsa(Ed25519KeyPair),
+        Rs256(RsaKeyPair),
+    }
+
+    /// A software authenticator: one credential, a signature counter and the flags it reports
+    struct SoftAuthenticator {
+        key: TestKey,
+        credential_id: Vec<u8>,
+        sign_count: u32,
+        flags: u8,
+    }
+
+    impl SoftAuthenticator {
+        fn new(algorithm: i64) -> Self {
+            let rng = SystemRandom::new();
+            let key = match algorithm {
+                COSE_ES256 => {
+                    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+                        .unwrap();
+                    TestKey::Es256(
+                        EcdsaKeyPair::from_pkcs8(
+                            &ECDSA_P256_SHA256_ASN1_SIGNING,
+                            pkcs8.as_ref(),
+                            &rng,
+                        )
+                        .unwrap(),
+                    )
+                }
+                COSE_EDDSA => {
+                    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+                    TestKey::EdDsa(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap())
+                }
+                // ring can't generate RSA keys
+                _ => TestKey::Rs256(
+                    RsaKeyPair::from_der(include_bytes!("testdata/webauthn-rs256.der")).unwrap(),
+                ),
+            };
+            SoftAuthenticator {
+                key,
+                credential_id: b"soft-credential".to_vec(),
+                sign_count: 0,
+                flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+            }
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let int = |v: i64| Value::Integer(v.into());
+            let entries = match &self.key {
+                TestKey::Es256(key) => {
+                    let point = key.public_key().as_ref();
+                    vec![
+                        (int(1), int(2)),
+                        (int(3), int(COSE_ES256)),
+                        (int(-1), int(1)),
+                        (int(-2), Value::Bytes(point[1..33].to_vec())),
+                        (int(-3), Value::Bytes(point[33..].to_vec())),
+                    ]
+                }
+                TestKey::EdDsa(key) => vec![
+                    (int(1), int(1)),
+                    (int(3), int(COSE_EDDSA)),
+                    (int(-1), int(6)),
+                    (int(-2), Value::Bytes(key.public_key().as_ref().to_vec())),
+                ],
+                TestKey::Rs256(key) => {
+                    let components = RsaPublicKeyComponents::<Vec<u8>>::from(key.public());
+                    vec![
+                        (int(1), int(3)),
+                        (int(3), int(COSE_RS256)),
+                        (int(-1), Value::Bytes(components.n)),
+                        (int(-2), Value::Bytes(components.e)),
+                    ]
+                }
+            };
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&Value::Map(entries), &mut bytes).unwrap();
+            bytes
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            let rng = SystemRandom::new();
+            match &self.key {
+                TestKey::Es256(key) => key.sign(&rng, message).unwrap().as_ref().to_vec(),
+                TestKey::EdDsa(key) => key.sign(message).as_ref().to_vec(),
+                TestKey::Rs256(key) => {
+                    let mut signature = vec![0; key.public().modulus_len()];
+                    key.sign(&RSA_PKCS1_SHA256, &rng, message, &mut signature)
+                        .unwrap();
+                    signature
+                }
+            }
+        }
+
+        fn algorithm(&self) -> i64 {
+            match self.key {
+                TestKey::Es256(_) => COSE_ES256,
+                TestKey::EdDsa(_) => COSE_EDDSA,
+                TestKey::Rs256(_) => COSE_RS256,
+            }
+        }
+
+        fn authenticator_data(&self, rp_id: &str, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            let flags = if attested {
+                self.flags | FLAG_ATTESTED_CREDENTIAL
+            } else {
+                self.flags
+            };
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            if attested {
+                data.extend_from_slice(&[0; 16]); // AAGUID
+                data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+                data.extend_from_slice(&self.credential_id);
+                data.extend_from_slice(&self.cose_key());
+            }
+            data
+        }
+
+        /// `navigator.credentials.create`: clientDataJSON and attestationObject, base64url
+        fn create(
+            &self,
+            fmt: &str,
+            challenge: &str,
+            rp_id: &str,
+            origin: &str,
+        ) -> (String, String) {
+            let client_data = client_data("webauthn.create", challenge, origin);
+            let auth_data = self.authenticator_data(rp_id, true);
+            let statement = match fmt {
+                "packed" => Value::Map(vec![
+                    (
+                        Value::Text("alg".into()),
+                        Value::Integer(self.algorithm().into()),
+                    ),
+                    (
+                        Value::Text("sig".into()),
+                        Value::Bytes(self.sign(&signed_data(&auth_data, &client_data))),
+                    ),
+                ]),
+                _ => Value::Map(Vec::new()),
+            };
+            let attestation = Value::Map(vec![
+                (Value::Text("fmt".into()), Value::Text(fmt.into())),
+                (Value::Text("attStmt".into()), statement),
+                (Value::Text("authData".into()), Value::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = Vec::new();
+            ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+            (
+                BASE64URL.encode(&client_data),
+                BASE64URL.encode(&attestation_object),
+            )
+        }
+
+        /// `navigator.credentials.get`: clientDataJSON, authenticatorData and signature, base64url
+        fn get(&mut self, challenge: &str, rp_id: &str, origin: &str) -> (String, String, String) {
+            self.sign_count += 1;
+            let client_data = client_data("webauthn.get", challenge, origin);
+            let auth_data = self.authenticator_data(rp_id, false);
+            let signature = self.sign(&signed_data(&auth_data, &client_data));
+            (
+                BASE64URL.encode(&client_data),
+                BASE64URL.encode(&auth_data),
+                BASE64URL.encode(&signature),
+            )
+        }
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({ "type": kind, "challenge": challenge, "origin": origin }))
+            .unwrap()
+    }
+
+    /// Register a credential, then log in with it twice
+    fn register_and_login(algorithm: i64, fmt: &str) {
+        let rp = rp();
+        let mut authenticator = SoftAuthenticator::new(algorithm);
+
+        let (client_data_json, attestation_object) =
+            authenticator.create(fmt, CHALLENGE, &rp.id, &rp.origin);
+        assert_eq!(client_challenge(&client_data_json).unwrap(), CHALLENGE);
+        let credential =
+            verify_registration(&client_data_json, &attestation_object, CHALLENGE, &rp).unwrap();
+        assert_eq!(
+            credential.credential_id,
+            BASE64URL.encode(&authenticator.credential_id)
+        );
+        assert_eq!(credential.algorithm, algorithm);
+
+        let mut stored_count = credential.sign_count as i64;
+        for _ in 0..2 {
+            let (client_data_json, auth_data, signature) =
+                authenticator.get(CHALLENGE, &rp.id, &rp.origin);
+            let sign_count = verify_assertion(
+                &client_data_json,
+                &auth_data,
+                &signature,
+                CHALLENGE,
+                &rp,
+                &credential.public_key,
+                true,
+            )
+            .unwrap() as i64;
+            assert!(sign_count_advances(stored_count, sign_count));
+            stored_count = sign_count;
+        }
+    }
+
+    #[test]
+    fn es256_packed_self_attestation_ceremony() {
+        register_and_login(COSE_ES256, "packed");
+    }
+
+    #[test]
+    fn eddsa_none_attestation_ceremony() {
+        register_and_login(COSE_EDDSA, "none");
+    }
+
+    #[test]
+    fn rs256_packed_self_attestation_ceremony() {
+        register_and_login(COSE_RS256, "packed");
+    }
+
+    /// A registered ES256 authenticator and its stored public key
+    fn registered() -> (SoftAuthenticator, String) {
+        let rp = rp();
+        let authenticator = SoftAuthenticator::new(COSE_ES256);
+        let (client_data_json, attestation_object) =
+            authenticator.create("packed", CHALLENGE, &rp.id, &rp.origin);
+        let credential =
+            verify_registration(&client_data_json, &attestation_object, CHALLENGE, &rp).unwrap();
+        (authenticator, credential.public_key)
+    }
+
+    fn assert_login(
+        assertion: (String, String, String),
+        public_key: &str,
+        require_user_verification: bool,
+    ) -> Result<u32, String> {
+        let (client_data_json, auth_data, signature) = assertion;
+        verify_assertion(
+            &client_data_json,
+            &auth_data,
+            &signature,
+            CHALLENGE,
+            &rp(),
+            public_key,
+            require_user_verification,
+        )
+    }
+
+    #[test]
+    fn registration_rejects_wrong_rp_id_hash() {
+        let rp = rp();
+        let authenticator = SoftAuthenticator::new(COSE_ES256);
+        let (client_data_json, attestation_object) =
+            authenticator.create("packed", CHALLENGE, "evil.example", &rp.origin);
+        assert_eq!(
+            verify_registration(&client_data_json, &attestation_object, CHALLENGE, &rp).err(),
+            Some("Credential belongs to another relying party".to_string())
+        );
+    }
+
+    #[test]
+    fn registration_rejects_wrong_challenge_and_origin() {
+        let rp = rp();
+        let authenticator = SoftAuthenticator::new(COSE_ES256);
+
+        let (client_data_json, attestation_object) =
+            authenticator.create("packed", "b3RoZXI", &rp.id, &rp.origin);
+        assert_eq!(
+            verify_registration(&client_data_json, &attestation_object, CHALLENGE, &rp).err(),
+            Some("Challenge mismatch".to_string())
+        );
+
+        let (client_data_json, attestation_object) =
+            authenticator.create("packed", CHALLENGE, &rp.id, "https://evil.example");
+        assert_eq!(
+            verify_registration(&client_data_json, &attestation_object, CHALLENGE, &rp).err(),
+            Some("Unexpected origin: https://evil.example".to_string())
+        );
+    }
+
+    #[test]
+    fn login_rejects_wrong_rp_id_hash() {
+        let (mut authenticator, public_key) = registered();
+        let assertion = authenticator.get(CHALLENGE, "evil.example", &rp().origin);
+        assert_eq!(
+            assert_login(assertion, &public_key, true),
+            Err("Credential belongs to another relying party".to_string())
+        );
+    }
+
+    #[test]
+    fn login_rejects_wrong_challenge_and_origin() {
+        let (mut authenticator, public_key) = registered();
+        let assertion = authenticator.get("b3RoZXI", &rp().id, &rp().origin);
+        assert_eq!(
+            assert_login(assertion, &public_key, true),
+            Err("Challenge mismatch".to_string())
+        );
+
+        let assertion = authenticator.get(CHALLENGE, &rp().id, "https://evil.example");
+        assert_eq!(
+            assert_login(assertion, &public_key, true),
+            Err("Unexpected origin: https://evil.example".to_string())
+        );
+    }
+
+    #[test]
+    fn login_rejects_registration_client_data() {
+        let (mut authenticator, public_key) = registered();
+        let (_, auth_data, _) = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        let client_data = client_data("webauthn.create", CHALLENGE, &rp().origin);
+        let auth_data_bytes = BASE64URL.decode(&auth_data).unwrap();
+        let signature = authenticator.sign(&signed_data(&auth_data_bytes, &client_data));
+        let assertion = (
+            BASE64URL.encode(&client_data),
+            auth_data,
+            BASE64URL.encode(&signature),
+        );
+        assert_eq!(
+            assert_login(assertion, &public_key, false),
+            Err("Expected a webauthn.get ceremony".to_string())
+        );
+    }
+
+    #[test]
+    fn passwordless_login_requires_user_verification() {
+        let (mut authenticator, public_key) = registered();
+        authenticator.flags = FLAG_USER_PRESENT;
+
+        let assertion = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        assert_eq!(
+            assert_login(assertion, &public_key, true),
+            Err("User verification is required".to_string())
+        );
+        // enough as a second factor after the password
+        let assertion = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        assert!(assert_login(assertion, &public_key, false).is_ok());
+    }
+
+    #[test]
+    fn login_requires_user_presence() {
+        let (mut authenticator, public_key) = registered();
+        authenticator.flags = 0;
+        let assertion = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        assert_eq!(
+            assert_login(assertion, &public_key, false),
+            Err("User presence is required".to_string())
+        );
+    }
+
+    #[test]
+    fn login_rejects_bad_signature() {
+        let (mut authenticator, public_key) = registered();
+        let (client_data_json, auth_data, _) = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        let mut auth_data_bytes = BASE64URL.decode(&auth_data).unwrap();
+        let signature = authenticator.sign(&signed_data(
+            &auth_data_bytes,
+            &BASE64URL.decode(&client_data_json).unwrap(),
+        ));
+        // the authenticator data was tampered with after signing
+        auth_data_bytes[36] ^= 0x01;
+        let assertion = (
+            client_data_json,
+            BASE64URL.encode(&auth_data_bytes),
+            BASE64URL.encode(&signature),
+        );
+        assert_eq!(
+            assert_login(assertion, &public_key, true),
+            Err("Invalid signature".to_string())
+        );
+
+        // a signature by another key
+        let (_, other_key) = registered();
+        let assertion = authenticator.get(CHALLENGE, &rp().id, &rp().origin);
+        assert_eq!(
+            assert_login(assertion, &other_key, true),
+            Err("Invalid signature".to_string())
+        );
+    }
+
+    #[test]
+    fn signature_counter_must_grow() {
+        let (mut authenticator, public_key) = registered();
+        authenticator.sign_count = 10;
+        let sign_count = assert_login(
+            authenticator.get(CHALLENGE, &rp().id, &rp().origin),
+            &public_key,
+            true,
+        )
+        .unwrap() as i64;
+        assert!(sign_count_advances(10, sign_count));
+
+        // a clone that is behind the original
+        authenticator.sign_count = 4;
+        let sign_count = assert_login(
+            authenticator.get(CHALLENGE, &rp().id, &rp().origin),
+            &public_key,
+            true,
+        )
+        .unwrap() as i64;
+        assert!(!sign_count_advances(11, sign_count));
+        assert!(!sign_count_advances(11, 11));
+        // authenticators without a counter always report 0
+        assert!(sign_count_advances(0, 0));
+        assert!(!sign_count_advances(5, 0));
+    }
+
+    #[test]
+    fn packed_attestation_with_a_bad_signature_is_rejected() {
+        let rp = rp();
+        let authenticator = SoftAuthenticator::new(COSE_EDDSA);
+        let (client_data_json, attestation_object) =
+            authenticator.create("packed", CHALLENGE, &rp.id, &rp.origin);
+        // the attestation signature was tampered with
+        let mut attestation: Value =
+            ciborium::from_reader(BASE64URL.decode(&attestation_object).unwrap().as_slice())
+                .unwrap();
+        if let Value::Map(entries) = &mut attestation
+            && let Some((_, Value::Map(statement))) = entries
+                .iter_mut()
+                .find(|(k, _)| k.as_text() == Some("attStmt"))
+            && let Some((_, Value::Bytes(sig))) = statement
+                .iter_mut()
+                .find(|(k, _)| k.as_text() == Some("sig"))
+        {
+            sig[0] ^= 0xff;
+        }
+        let mut tampered = Vec::new();
+        ciborium::into_writer(&attestation, &mut tampered).unwrap();
+        assert_eq!(
+            verify_registration(
+                &client_data_json,
+                &BASE64URL.encode(&tampered),
+                CHALLENGE,
+                &rp
+            )
+            .err(),
+            Some("Invalid signature".to_string())
+        );
+    }
+
+    #[test]
+    fn padded_credential_ids_match_the_stored_form() {
+        let stored = BASE64URL.encode([0xfb, 0xff]);
+        assert_eq!(normalize_credential_id("-_8="), stored);
+        assert_eq!(normalize_credential_id(&stored), stored);
+    }
+}